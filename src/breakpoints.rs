@@ -0,0 +1,42 @@
+use std::ops;
+
+use crate::{Hwbp, Index};
+
+/// All four hardware breakpoints of a context, indexed by [`Index`].
+///
+/// The breakpoint in each slot is expected to carry that slot's index, which is the case for any
+/// [`Breakpoints`] returned by [`HwbpContext::all`](crate::HwbpContext::all).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoints(pub [Hwbp; 4]);
+
+impl ops::Index<Index> for Breakpoints {
+    type Output = Hwbp;
+
+    fn index(&self, index: Index) -> &Hwbp {
+        &self.0[index as usize]
+    }
+}
+
+impl ops::IndexMut<Index> for Breakpoints {
+    fn index_mut(&mut self, index: Index) -> &mut Hwbp {
+        &mut self.0[index as usize]
+    }
+}
+
+impl IntoIterator for Breakpoints {
+    type Item = Hwbp;
+    type IntoIter = std::array::IntoIter<Hwbp, 4>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Breakpoints {
+    type Item = &'a Hwbp;
+    type IntoIter = std::slice::Iter<'a, Hwbp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,157 @@
+//! C ABI exports, for using `hwbp` from non-Rust code (e.g. C/C++ DLLs).
+//!
+//! Only available with the `capi` feature enabled.
+//!
+//! Every function returns `0` on success. If the underlying Windows API call fails, the value of
+//! `GetLastError` is returned instead. Invalid arguments return `ERROR_INVALID_PARAMETER`. Any
+//! other failure, or a failed call that didn't set a last error, returns one of the negative
+//! `HWBP_ERROR_*` codes, so a failure is never reported as `0`.
+//!
+//! The matching C declarations are:
+//! ```c
+//! #define HWBP_ERROR_FETCH_CONTEXT         -1
+//! #define HWBP_ERROR_APPLY_CONTEXT         -2
+//! #define HWBP_ERROR_SUSPEND_THREAD        -3
+//! #define HWBP_ERROR_ADD_HANDLER           -4
+//! #define HWBP_ERROR_VERIFICATION          -5
+//! #define HWBP_ERROR_SNAPSHOT              -6
+//! #define HWBP_ERROR_UNSUPPORTED_CONDITION -7
+//! #define HWBP_ERROR_MODULE_NOT_FOUND      -8
+//!
+//! typedef struct {
+//!     const void* address;
+//!     uint8_t size_bits;      // Two-bit size encoding used in Dr7, see `Size::as_bits`.
+//!     uint8_t condition_bits; // Two-bit condition encoding used in Dr7, see `Condition::as_bits`.
+//!     bool enabled;
+//! } HwbpInfo;
+//!
+//! int32_t hwbp_set(uint8_t index, const void* address, uint8_t size_bits, uint8_t condition_bits, bool enabled);
+//! int32_t hwbp_clear_all(void);
+//! int32_t hwbp_get(uint8_t index, HwbpInfo* out);
+//! ```
+//!
+//! All functions operate on the calling thread.
+
+use std::{convert::TryFrom, ffi::c_void};
+
+use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+use winapi::um::errhandlingapi::GetLastError;
+
+use crate::{Condition, Hwbp, HwbpContext, HwbpError, Index, Size};
+
+/// C representation of a [`Hwbp`], as written by [`hwbp_get`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HwbpInfo {
+    pub address: *const c_void,
+    pub size_bits: u8,
+    pub condition_bits: u8,
+    pub enabled: bool,
+}
+
+/// Returned for [`HwbpError::FailedFetchContext`] if no last error was set.
+pub const HWBP_ERROR_FETCH_CONTEXT: i32 = -1;
+/// Returned for [`HwbpError::FailedApplyContext`] if no last error was set.
+pub const HWBP_ERROR_APPLY_CONTEXT: i32 = -2;
+/// Returned for [`HwbpError::FailedSuspendThread`] if no last error was set.
+pub const HWBP_ERROR_SUSPEND_THREAD: i32 = -3;
+/// Returned for [`HwbpError::FailedAddHandler`] if no last error was set.
+pub const HWBP_ERROR_ADD_HANDLER: i32 = -4;
+/// Returned for [`HwbpError::VerificationFailed`].
+pub const HWBP_ERROR_VERIFICATION: i32 = -5;
+/// Returned for [`HwbpError::FailedSnapshot`] if no last error was set.
+pub const HWBP_ERROR_SNAPSHOT: i32 = -6;
+/// Returned for [`HwbpError::UnsupportedCondition`].
+pub const HWBP_ERROR_UNSUPPORTED_CONDITION: i32 = -7;
+/// Returned for [`HwbpError::ModuleNotFound`].
+pub const HWBP_ERROR_MODULE_NOT_FOUND: i32 = -8;
+
+fn status(result: Result<(), HwbpError>) -> i32 {
+    let error = match result {
+        Ok(()) => return 0,
+        Err(error) => error,
+    };
+
+    // Only failed Windows API calls set a last error, anything else could see a stale one
+    let (code, last_error) = match error {
+        HwbpError::FailedFetchContext => (HWBP_ERROR_FETCH_CONTEXT, true),
+        HwbpError::FailedApplyContext => (HWBP_ERROR_APPLY_CONTEXT, true),
+        HwbpError::FailedSuspendThread => (HWBP_ERROR_SUSPEND_THREAD, true),
+        HwbpError::FailedAddHandler => (HWBP_ERROR_ADD_HANDLER, true),
+        HwbpError::VerificationFailed => (HWBP_ERROR_VERIFICATION, false),
+        HwbpError::FailedSnapshot => (HWBP_ERROR_SNAPSHOT, true),
+        HwbpError::UnsupportedCondition => (HWBP_ERROR_UNSUPPORTED_CONDITION, false),
+        HwbpError::ModuleNotFound => (HWBP_ERROR_MODULE_NOT_FOUND, false),
+    };
+
+    match last_error.then(|| unsafe { GetLastError() }) {
+        Some(0) | None => code,
+        Some(last_error) => last_error as i32,
+    }
+}
+
+/// Configures and applies the breakpoint at `index` (`0..=3`) on the calling thread.
+///
+/// # Safety
+/// See [`Hwbp::apply`].
+#[no_mangle]
+pub unsafe extern "C" fn hwbp_set(
+    index: u8,
+    address: *const c_void,
+    size_bits: u8,
+    condition_bits: u8,
+    enabled: bool,
+) -> i32 {
+    let index = Index::try_from(index);
+    let size = Size::from_bits(size_bits);
+    let condition = Condition::from_bits(condition_bits);
+
+    let (index, size, condition) = match (index, size, condition) {
+        (Ok(index), Some(size), Some(condition)) => (index, size, condition),
+        _ => return ERROR_INVALID_PARAMETER as i32,
+    };
+
+    status(
+        Hwbp::from_index(index)
+            .with_address(address)
+            .with_size(size)
+            .with_condition(condition)
+            .with_enabled(enabled)
+            .apply(),
+    )
+}
+
+/// Clears all hardware breakpoints on the calling thread.
+///
+/// # Safety
+/// See [`HwbpContext::apply`].
+#[no_mangle]
+pub unsafe extern "C" fn hwbp_clear_all() -> i32 {
+    status(HwbpContext::get().and_then(|mut context| {
+        context.clear_breakpoints();
+        context.apply()
+    }))
+}
+
+/// Reads the breakpoint at `index` (`0..=3`) on the calling thread into `out`.
+///
+/// # Safety
+/// `out` must either be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn hwbp_get(index: u8, out: *mut HwbpInfo) -> i32 {
+    let index = match Index::try_from(index) {
+        Ok(index) if !out.is_null() => index,
+        _ => return ERROR_INVALID_PARAMETER as i32,
+    };
+
+    status(HwbpContext::get().map(|context| {
+        let bp = context.breakpoint(index);
+
+        out.write(HwbpInfo {
+            address: bp.address,
+            size_bits: bp.size.as_bits(),
+            condition_bits: bp.condition.as_bits(),
+            enabled: bp.enabled,
+        });
+    }))
+}
@@ -1,13 +1,49 @@
-use winapi::um::processthreadsapi::{GetThreadContext, SetThreadContext};
+use winapi::um::processthreadsapi::{
+    GetCurrentThreadId, GetThreadContext, GetThreadId, ResumeThread, SetThreadContext,
+    SuspendThread,
+};
 use winapi::um::winnt::{RtlCaptureContext, CONTEXT, HANDLE};
 
 use crate::HwbpError;
 
-fn current_thread() -> HANDLE {
+/// Returns the pseudo-handle of the current thread, for use with the `*Other(HANDLE)` variants.
+///
+/// This is the same value `GetCurrentThread` returns. It's not a real handle, but a constant
+/// that always refers to whichever thread uses it, so it doesn't need to be closed, and it can't
+/// be passed to another thread to refer to this one.
+///
+/// ```
+/// # use hwbp::{HwbpContext, context::{self, FetchWith}};
+/// HwbpContext::get_with(FetchWith::GetThreadContextOther(context::current_thread()))
+///     .expect("failed to get context");
+/// ```
+pub fn current_thread() -> HANDLE {
     // WinAPI's GetCurrentThread() only calls NtCurrentThread(), which is hardcoded to always returns -2.
     -2 as _
 }
 
+/// Runs `f` while the thread behind `handle` is suspended.
+///
+/// If `handle` refers to the calling thread it isn't suspended, as nothing would resume it.
+pub(crate) fn with_suspended<R>(
+    handle: HANDLE,
+    f: impl FnOnce() -> Result<R, HwbpError>,
+) -> Result<R, HwbpError> {
+    unsafe {
+        if GetThreadId(handle) == GetCurrentThreadId() {
+            return f();
+        }
+
+        if SuspendThread(handle) == u32::MAX {
+            return Err(HwbpError::FailedSuspendThread);
+        }
+
+        let result = f();
+        ResumeThread(handle);
+        result
+    }
+}
+
 pub trait FetchContext {
     fn fetch_context(self, context: &mut CONTEXT) -> Result<(), HwbpError>;
 }
@@ -16,12 +52,67 @@ pub trait ApplyContext {
     fn apply_context(self, context: &CONTEXT) -> Result<(), HwbpError>;
 }
 
+/// A single target that contexts are both fetched from and applied to, such as the thread being
+/// instrumented.
+///
+/// Unlike [`FetchContext`] and [`ApplyContext`], which are consumed by each operation, a source is
+/// used through a shared reference, so it can be passed around as a `&dyn ContextSource`. See
+/// [`HwbpContext::get_from`](crate::HwbpContext::get_from) and
+/// [`HwbpContext::apply_to`](crate::HwbpContext::apply_to).
+pub trait ContextSource {
+    fn fetch(&self, context: &mut CONTEXT) -> Result<(), HwbpError>;
+    fn apply(&self, context: &CONTEXT) -> Result<(), HwbpError>;
+}
+
+impl FetchContext for &dyn ContextSource {
+    fn fetch_context(self, context: &mut CONTEXT) -> Result<(), HwbpError> {
+        self.fetch(context)
+    }
+}
+
+impl ApplyContext for &dyn ContextSource {
+    fn apply_context(self, context: &CONTEXT) -> Result<(), HwbpError> {
+        self.apply(context)
+    }
+}
+
+/// The thread behind a handle, which is suspended while its context is fetched or applied, like
+/// [`Hwbp::apply_to_thread`](crate::Hwbp::apply_to_thread) does.
+///
+/// The handle needs `THREAD_GET_CONTEXT`, `THREAD_SET_CONTEXT`, and `THREAD_SUSPEND_RESUME` access,
+/// and isn't closed by this.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ThreadHandleSource(pub HANDLE);
+
+impl ContextSource for ThreadHandleSource {
+    fn fetch(&self, context: &mut CONTEXT) -> Result<(), HwbpError> {
+        with_suspended(self.0, || {
+            FetchWith::GetThreadContextOther(self.0).fetch_context(context)
+        })
+    }
+
+    fn apply(&self, context: &CONTEXT) -> Result<(), HwbpError> {
+        with_suspended(self.0, || {
+            ApplyWith::SetThreadContextOther(self.0).apply_context(context)
+        })
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ApplyWith {
     SetThreadContext,
     SetThreadContextOther(HANDLE),
+    /// Applies the context with `RtlRestoreContext`, which also resumes execution at the
+    /// instruction pointer stored in the context.
+    ///
+    /// Only available on 64-bit, as `ntdll.dll` doesn't export `RtlRestoreContext` on 32-bit
+    /// Windows. Use `ApplyWith::NtContinue` there instead.
     #[cfg(target_arch = "x86_64")]
     RtlRestoreContext,
+    /// Applies the context with `NtContinue`, which also resumes execution at the instruction
+    /// pointer stored in the context.
+    ///
+    /// Available on both 32-bit and 64-bit, behind the `ntapi` feature.
     #[cfg(feature = "ntapi")]
     NtContinue,
 }
@@ -35,7 +126,7 @@ pub enum FetchWith {
 
 impl FetchContext for FetchWith {
     fn fetch_context(self, context: &mut CONTEXT) -> Result<(), HwbpError> {
-        unsafe {
+        let result = unsafe {
             match self {
                 FetchWith::RtlCaptureContext => Ok(RtlCaptureContext(context)),
                 FetchWith::GetThreadContext => match GetThreadContext(current_thread(), context) {
@@ -47,14 +138,16 @@ impl FetchContext for FetchWith {
                     _ => Ok(()),
                 },
             }
-        }
+        };
+
+        log_op!("fetch", self, result);
+        result
     }
 }
 
 impl ApplyContext for ApplyWith {
     fn apply_context(self, context: &CONTEXT) -> Result<(), HwbpError> {
-        let as_mut = context as *const CONTEXT as *mut CONTEXT;
-        unsafe {
+        let result = unsafe {
             match self {
                 ApplyWith::SetThreadContext => match SetThreadContext(current_thread(), context) {
                     0 => Err(HwbpError::FailedApplyContext),
@@ -68,6 +161,7 @@ impl ApplyContext for ApplyWith {
                 ApplyWith::RtlRestoreContext => {
                     use winapi::um::winnt::RtlRestoreContext;
 
+                    let as_mut = context as *const CONTEXT as *mut CONTEXT;
                     Ok(RtlRestoreContext(as_mut, std::ptr::null_mut()))
                 }
                 #[cfg(feature = "ntapi")]
@@ -75,12 +169,16 @@ impl ApplyContext for ApplyWith {
                     use ntapi::ntxcapi::NtContinue;
                     use winapi::shared::ntdef::NT_SUCCESS;
 
+                    let as_mut = context as *const CONTEXT as *mut CONTEXT;
                     match NT_SUCCESS(NtContinue(as_mut, 1)) {
                         true => Ok(()),
                         false => Err(HwbpError::FailedApplyContext),
                     }
                 }
             }
-        }
+        };
+
+        log_op!("apply", self, result);
+        result
     }
 }
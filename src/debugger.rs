@@ -0,0 +1,145 @@
+//! A higher-level wrapper that manages the exception handler and breakpoints together.
+
+use winapi::shared::ntdef::PVOID;
+use winapi::um::errhandlingapi::{AddVectoredExceptionHandler, RemoveVectoredExceptionHandler};
+use winapi::um::minwinbase::EXCEPTION_SINGLE_STEP;
+use winapi::um::winnt::{LONG, PEXCEPTION_POINTERS};
+use winapi::vc::excpt::{EXCEPTION_CONTINUE_EXECUTION, EXCEPTION_CONTINUE_SEARCH};
+
+use crate::{
+    dispatcher::{Callback, Dispatcher},
+    Hwbp, HwbpContext, HwbpError, Index,
+};
+
+/// Owns an exception handler and the breakpoints added through it.
+///
+/// Breakpoints are placed on the calling thread, and their callbacks are registered with
+/// [`Dispatcher::global`]. When dropped, every breakpoint added through the debugger is cleared
+/// and unregistered, and then the exception handler is removed. If the breakpoints can't be
+/// cleared, the handler and callbacks are left installed, so that hitting them doesn't crash.
+///
+/// ```no_run
+/// # use winapi::um::winnt::CONTEXT;
+/// # use hwbp::{Condition, Debugger, Hwbp, HwbpContext};
+/// static mut WATCHED: u32 = 0;
+///
+/// fn on_write(_context: &mut HwbpContext<&mut CONTEXT>, _hwbp: Hwbp) {
+///     // [Handle the write here.]
+/// }
+///
+/// let mut debugger = Debugger::new().expect("failed to install exception handler");
+/// let bp = Hwbp::first()
+///     .with_address(unsafe { std::ptr::addr_of!(WATCHED) })
+///     .with_condition(Condition::Write);
+///
+/// unsafe { debugger.add(bp, on_write) }
+///     .expect("failed to add breakpoint")
+///     .expect("no unused breakpoints");
+/// ```
+pub struct Debugger {
+    handler: PVOID,
+    owned: [bool; 4],
+}
+
+impl Debugger {
+    /// Installs the exception handler.
+    pub fn new() -> Result<Self, HwbpError> {
+        let handler = unsafe { AddVectoredExceptionHandler(1, Some(handler)) };
+
+        if handler.is_null() {
+            return Err(HwbpError::FailedAddHandler);
+        }
+
+        Ok(Self {
+            handler,
+            owned: [false; 4],
+        })
+    }
+
+    /// Places `hwbp` in an unused slot on the calling thread and calls `callback` when it's hit.
+    ///
+    /// The index of `hwbp` is ignored, and the breakpoint is always enabled. A slot is only
+    /// considered unused if neither its local nor its global enable bit is set on this thread,
+    /// and it has no callback registered with [`Dispatcher::global`]. Returns the placed breakpoint, or [`None`] if no slot was unused.
+    ///
+    /// # Safety
+    /// See [`Hwbp::apply`].
+    pub unsafe fn add(
+        &mut self,
+        hwbp: Hwbp,
+        callback: Callback,
+    ) -> Result<Option<Hwbp>, HwbpError> {
+        let dispatcher = Dispatcher::global();
+        let mut context = HwbpContext::get()?;
+
+        let index = [Index::First, Index::Second, Index::Third, Index::Fourth]
+            .into_iter()
+            .find(|&index| {
+                !context.breakpoint(index).is_in_use() && dispatcher.callback(index).is_none()
+            });
+
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        // Register the callback first, so the breakpoint can't be hit without one
+        let hwbp = hwbp.with_index(index).with_enabled(true);
+        dispatcher.register(index, callback);
+
+        context.set_breakpoint(hwbp);
+        if let Err(error) = context.apply() {
+            dispatcher.unregister(index);
+            return Err(error);
+        }
+
+        self.owned[index as usize] = true;
+        Ok(Some(hwbp))
+    }
+}
+
+impl Drop for Debugger {
+    fn drop(&mut self) {
+        let cleared = HwbpContext::get().and_then(|mut context| {
+            for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+                if self.owned[index as usize] {
+                    context.clear_breakpoint(index);
+                }
+            }
+
+            unsafe { context.apply() }
+        });
+
+        // A breakpoint that's still placed would crash the thread when hit without a handler, so
+        // leave the handler and callbacks installed instead
+        if cleared.is_err() {
+            return;
+        }
+
+        let dispatcher = Dispatcher::global();
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            if self.owned[index as usize] {
+                dispatcher.unregister(index);
+            }
+        }
+
+        unsafe { RemoveVectoredExceptionHandler(self.handler) };
+    }
+}
+
+unsafe extern "system" fn handler(ex: PEXCEPTION_POINTERS) -> LONG {
+    if let Some(ex) = ex.as_ref() {
+        let cr = ex.ContextRecord.as_mut();
+        let er = ex.ExceptionRecord.as_ref();
+
+        if let (Some(cr), Some(er)) = (cr, er) {
+            if er.ExceptionCode == EXCEPTION_SINGLE_STEP
+                && Dispatcher::global().dispatch(&mut HwbpContext::from_context(cr))
+            {
+                return EXCEPTION_CONTINUE_EXECUTION;
+            }
+        }
+    }
+
+    EXCEPTION_CONTINUE_SEARCH
+}
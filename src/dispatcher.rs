@@ -0,0 +1,433 @@
+//! Routing of hardware breakpoint exceptions to per-breakpoint callbacks.
+//!
+//! A [`Dispatcher`] holds one callback per breakpoint index. When a breakpoint is hit, the
+//! callback registered for its index is called from inside the exception handler.
+//!
+//! This crate still doesn't register any exception handlers on its own. To route exceptions to
+//! [`Dispatcher::global`], register [`vectored_handler`] yourself:
+//! ```no_run
+//! # unsafe {
+//! # use winapi::um::errhandlingapi::AddVectoredExceptionHandler;
+//! # use winapi::um::winnt::CONTEXT;
+//! # use hwbp::{dispatcher::{self, Dispatcher}, Hwbp, HwbpContext, Index};
+//! fn on_hit(context: &mut HwbpContext<&mut CONTEXT>, hwbp: Hwbp) {
+//!     // [Inspect or modify the context here.]
+//! }
+//!
+//! Dispatcher::global().register(Index::First, on_hit);
+//! AddVectoredExceptionHandler(1, Some(dispatcher::vectored_handler));
+//! # }
+//! ```
+//!
+//! # Reentrancy
+//! Callbacks run inside the exception handler, on whichever thread hit the breakpoint, at any
+//! point in that thread's execution. That thread might be holding any lock at the time, including
+//! ones inside the allocator or `std`, so taking a lock inside the handler can deadlock.
+//!
+//! To avoid that, the dispatcher itself never locks or allocates. Every slot is a single atomic
+//! function pointer, so registering a callback is one atomic swap, and the handler sees either the
+//! old or the new callback, never a partially written one. The same rules apply to the callbacks:
+//! don't lock, don't allocate, and keep them short.
+//!
+//! The optional hit log follows the same rules. Its ring buffer is part of the dispatcher, so
+//! recording a hit only writes a few atomics, see [`Dispatcher::set_hit_log`].
+
+use std::{
+    cell::Cell,
+    ptr::null_mut,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use winapi::um::minwinbase::EXCEPTION_SINGLE_STEP;
+use winapi::um::winnt::{CONTEXT, LONG, PEXCEPTION_POINTERS};
+use winapi::vc::excpt::{EXCEPTION_CONTINUE_EXECUTION, EXCEPTION_CONTINUE_SEARCH};
+
+use crate::{Hwbp, HwbpContext, Index};
+
+/// How many hits the log of a [`Dispatcher`] holds before the oldest ones are overwritten.
+pub const HIT_LOG_CAPACITY: usize = 64;
+
+/// A breakpoint hit recorded in the hit log, see [`Dispatcher::drain_log`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HitRecord {
+    pub index: Index,
+    /// The instruction pointer of the context, see [`HwbpContext::instruction_pointer`].
+    pub instruction_pointer: usize,
+    pub timestamp: Instant,
+}
+
+/// The point in time hit log timestamps are stored relative to, set when a log is first enabled.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// One entry of the hit log, guarded by a sequence number like a seqlock.
+///
+/// `seq` is `pos + 1` while the slot holds the hit at position `pos` of the log, and zero while
+/// it's being written.
+struct HitSlot {
+    seq: AtomicU64,
+    index: AtomicU8,
+    instruction_pointer: AtomicUsize,
+    nanos: AtomicU64,
+}
+
+impl HitSlot {
+    const fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            index: AtomicU8::new(0),
+            instruction_pointer: AtomicUsize::new(0),
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn write(&self, pos: u64, index: Index, instruction_pointer: usize, nanos: u64) {
+        self.seq.store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        self.index.store(index as u8, Ordering::Relaxed);
+        self.instruction_pointer
+            .store(instruction_pointer, Ordering::Relaxed);
+        self.nanos.store(nanos, Ordering::Relaxed);
+
+        self.seq.store(pos + 1, Ordering::Release);
+    }
+
+    /// Returns the hit at position `pos`, unless the slot was overwritten or is still being written.
+    fn read(&self, pos: u64, epoch: Instant) -> Option<HitRecord> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq != pos + 1 {
+            return None;
+        }
+
+        let index = self.index.load(Ordering::Relaxed);
+        let instruction_pointer = self.instruction_pointer.load(Ordering::Relaxed);
+        let nanos = self.nanos.load(Ordering::Relaxed);
+
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+
+        Some(HitRecord {
+            index: Index::try_from(index).ok()?,
+            instruction_pointer,
+            timestamp: epoch + Duration::from_nanos(nanos),
+        })
+    }
+}
+
+/// A function called for each instruction single-stepped after [`HwbpContext::step_count`].
+///
+/// The second argument is how many instructions are left to step after this one.
+pub type StepCallback = fn(&mut HwbpContext<&mut CONTEXT>, u32);
+
+thread_local! {
+    /// How many more single-step exceptions the current thread expects, see
+    /// [`HwbpContext::step_count`].
+    static STEPS_LEFT: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Sets how many more instructions the current thread single-steps.
+pub(crate) fn set_steps_left(steps: u32) {
+    STEPS_LEFT.with(|left| left.set(steps));
+}
+
+/// Returns how many more instructions the current thread single-steps.
+pub fn steps_left() -> u32 {
+    STEPS_LEFT.with(Cell::get)
+}
+
+/// A function called when the breakpoint it's registered for is hit.
+///
+/// The context is the exception's context record, which gets applied when the handler returns.
+pub type Callback = fn(&mut HwbpContext<&mut CONTEXT>, Hwbp);
+
+/// A set of callbacks, one per breakpoint index.
+pub struct Dispatcher {
+    callbacks: [AtomicPtr<()>; 4],
+    step_callback: AtomicPtr<()>,
+    hits: [AtomicU64; 4],
+    disarm: AtomicBool,
+    log_enabled: AtomicBool,
+    log_head: AtomicU64,
+    log_tail: AtomicU64,
+    log: [HitSlot; HIT_LOG_CAPACITY],
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dispatcher {
+    /// Creates a dispatcher with no callbacks registered.
+    ///
+    /// Most users want [`Dispatcher::global`] instead, which is what [`vectored_handler`] uses.
+    pub const fn new() -> Self {
+        Self {
+            callbacks: [
+                AtomicPtr::new(null_mut()),
+                AtomicPtr::new(null_mut()),
+                AtomicPtr::new(null_mut()),
+                AtomicPtr::new(null_mut()),
+            ],
+            step_callback: AtomicPtr::new(null_mut()),
+            hits: [
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+                AtomicU64::new(0),
+            ],
+            disarm: AtomicBool::new(true),
+            log_enabled: AtomicBool::new(false),
+            log_head: AtomicU64::new(0),
+            log_tail: AtomicU64::new(0),
+            log: [const { HitSlot::new() }; HIT_LOG_CAPACITY],
+        }
+    }
+
+    /// Returns the process-wide dispatcher used by [`vectored_handler`].
+    pub fn global() -> &'static Dispatcher {
+        static GLOBAL: OnceLock<Dispatcher> = OnceLock::new();
+        GLOBAL.get_or_init(Dispatcher::new)
+    }
+
+    /// Registers `callback` for `index`, returning the callback it replaced.
+    pub fn register(&self, index: Index, callback: Callback) -> Option<Callback> {
+        Self::decode(self.callbacks[index as usize].swap(callback as *mut (), Ordering::AcqRel))
+    }
+
+    /// Removes the callback for `index`, returning it.
+    pub fn unregister(&self, index: Index) -> Option<Callback> {
+        Self::decode(self.callbacks[index as usize].swap(null_mut(), Ordering::AcqRel))
+    }
+
+    /// Returns the callback currently registered for `index`.
+    pub fn callback(&self, index: Index) -> Option<Callback> {
+        Self::decode(self.callbacks[index as usize].load(Ordering::Acquire))
+    }
+
+    /// Registers `callback` to be called for every instruction single-stepped after
+    /// [`HwbpContext::step_count`], returning the callback it replaced.
+    pub fn register_step(&self, callback: StepCallback) -> Option<StepCallback> {
+        Self::decode_step(
+            self.step_callback
+                .swap(callback as *mut (), Ordering::AcqRel),
+        )
+    }
+
+    /// Removes the step callback, returning it.
+    pub fn unregister_step(&self) -> Option<StepCallback> {
+        Self::decode_step(self.step_callback.swap(null_mut(), Ordering::AcqRel))
+    }
+
+    /// Returns how many times the callback of each index has been called.
+    pub fn counts(&self) -> [u64; 4] {
+        [
+            self.hits[0].load(Ordering::Relaxed),
+            self.hits[1].load(Ordering::Relaxed),
+            self.hits[2].load(Ordering::Relaxed),
+            self.hits[3].load(Ordering::Relaxed),
+        ]
+    }
+
+    /// Resets the counts returned by [`Dispatcher::counts`] to zero.
+    pub fn reset_counts(&self) {
+        for hits in &self.hits {
+            hits.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Sets whether breakpoints are disarmed while the callback of a data breakpoint runs, which
+    /// is enabled by default.
+    ///
+    /// A callback reading the memory watched by a [`Condition::ReadWrite`] breakpoint would
+    /// otherwise trigger it again from inside the exception handler, and recurse until the stack
+    /// runs out. While disarmed, every breakpoint is disabled both in the context passed to the
+    /// callback and on the thread itself. They're enabled again in the context afterwards, which
+    /// the thread resumes with.
+    ///
+    /// If the callback changes a breakpoint itself, its changes to that breakpoint are kept as
+    /// they are instead, while the others are still enabled again. If the breakpoints can't be
+    /// disabled on the thread, the callback is skipped for that hit and isn't counted in
+    /// [`Dispatcher::counts`] or the hit log.
+    /// Execution breakpoints are never disarmed, as the callback can't execute the instruction
+    /// they're placed on.
+    ///
+    /// [`Condition::ReadWrite`]: crate::Condition::ReadWrite
+    pub fn set_disarm_during_callback(&self, disarm: bool) {
+        self.disarm.store(disarm, Ordering::Relaxed);
+    }
+
+    /// Enables or disables the hit log, which is disabled by default.
+    ///
+    /// While enabled, every callback call is recorded in a ring buffer of [`HIT_LOG_CAPACITY`]
+    /// hits, overwriting the oldest hits once it's full. Recording never allocates or locks.
+    /// Disabling the log keeps the hits recorded so far.
+    pub fn set_hit_log(&self, enabled: bool) {
+        EPOCH.get_or_init(Instant::now);
+        self.log_enabled.store(enabled, Ordering::Release);
+    }
+
+    /// Removes and returns the hits recorded since the last drain, oldest first.
+    ///
+    /// Hits that were overwritten, or that are still being recorded by another thread while
+    /// draining, are left out.
+    pub fn drain_log(&self) -> Vec<HitRecord> {
+        let epoch = match EPOCH.get() {
+            Some(epoch) => *epoch,
+            None => return Vec::new(),
+        };
+
+        let head = self.log_head.load(Ordering::Acquire);
+        let tail = self.log_tail.swap(head, Ordering::AcqRel);
+        let start = tail.max(head.saturating_sub(HIT_LOG_CAPACITY as u64));
+
+        (start..head)
+            .filter_map(|pos| self.log[(pos % HIT_LOG_CAPACITY as u64) as usize].read(pos, epoch))
+            .collect()
+    }
+
+    fn record(&self, index: Index, instruction_pointer: usize) {
+        if !self.log_enabled.load(Ordering::Acquire) {
+            return;
+        }
+
+        if let Some(epoch) = EPOCH.get() {
+            let nanos = Instant::now().saturating_duration_since(*epoch).as_nanos() as u64;
+            let pos = self.log_head.fetch_add(1, Ordering::AcqRel);
+            self.log[(pos % HIT_LOG_CAPACITY as u64) as usize].write(
+                pos,
+                index,
+                instruction_pointer,
+                nanos,
+            );
+        }
+    }
+
+    /// Returns whether the callback was called, which it isn't if disarming the thread failed.
+    fn call_disarmed(
+        context: &mut HwbpContext<&mut CONTEXT>,
+        hwbp: Hwbp,
+        callback: Callback,
+    ) -> bool {
+        let armed = context.disable_all();
+        let disarmed = context.read_table();
+
+        // The context is only applied once the handler returns, so the thread itself still has
+        // the breakpoints armed until they're applied to it directly. If that fails, the callback
+        // would trigger them again, so it's skipped.
+        let called = unsafe { context.apply_debug_registers_only() }.is_ok();
+        if called {
+            callback(context, hwbp);
+        }
+
+        // Slots the callback changed keep its changes, every other slot is armed again.
+        let after = context.read_table();
+        let mut dr7 = context.dr7_mut();
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            if after[index as usize] == disarmed[index as usize] {
+                dr7.set_enabled_local(index, armed.local[index as usize]);
+                dr7.set_enabled_global(index, armed.global[index as usize]);
+            }
+        }
+
+        called
+    }
+
+    fn decode(ptr: *mut ()) -> Option<Callback> {
+        // Only null and pointers created from a `Callback` are ever stored.
+        (!ptr.is_null()).then(|| unsafe { std::mem::transmute::<*mut (), Callback>(ptr) })
+    }
+
+    fn decode_step(ptr: *mut ()) -> Option<StepCallback> {
+        // Only null and pointers created from a `StepCallback` are ever stored.
+        (!ptr.is_null()).then(|| unsafe { std::mem::transmute::<*mut (), StepCallback>(ptr) })
+    }
+
+    /// Calls the callback of every breakpoint that triggered the exception.
+    ///
+    /// If the current thread is single-stepping after [`HwbpContext::step_count`], a single-step
+    /// exception counts down the instructions left to step, calls the step callback, and sets the
+    /// trap flag again until none are left.
+    ///
+    /// If any callback was called or a step was counted, the hit is acknowledged with
+    /// [`HwbpContext::acknowledge`] and `true` is returned. That resets
+    /// [`Dr6`](crate::registers::Dr6), and sets the resume flag if an execution breakpoint was hit,
+    /// so it doesn't trigger again as soon as execution continues. Otherwise the context is left
+    /// untouched, so that other handlers can inspect it.
+    pub fn dispatch(&self, context: &mut HwbpContext<&mut CONTEXT>) -> bool {
+        let mut handled = false;
+
+        if context.dr6().single_step() && steps_left() > 0 {
+            let left = steps_left() - 1;
+            set_steps_left(left);
+
+            if let Some(callback) = Self::decode_step(self.step_callback.load(Ordering::Acquire)) {
+                callback(context, left);
+            }
+
+            // Read it again, in case the callback extended or cancelled the stepping
+            context.eflags_mut().set_trap(steps_left() > 0);
+            handled = true;
+        }
+
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            if !context.dr6().breakpoint_at(index) {
+                continue;
+            }
+
+            if let Some(callback) = self.callback(index) {
+                let instruction_pointer = context.instruction_pointer();
+                let hwbp = context.breakpoint(index);
+
+                let called = if hwbp.condition.is_data() && self.disarm.load(Ordering::Relaxed) {
+                    Self::call_disarmed(context, hwbp, callback)
+                } else {
+                    callback(context, hwbp);
+                    true
+                };
+
+                if called {
+                    self.hits[index as usize].fetch_add(1, Ordering::Relaxed);
+                    self.record(index, instruction_pointer);
+                }
+                handled = true;
+            }
+        }
+
+        if handled {
+            context.acknowledge();
+        }
+
+        handled
+    }
+}
+
+/// A vectored exception handler that dispatches hardware breakpoint hits to [`Dispatcher::global`].
+///
+/// Exceptions without a registered callback are passed on to the next handler.
+///
+/// # Safety
+/// Only meant to be called by Windows, see `AddVectoredExceptionHandler`.
+pub unsafe extern "system" fn vectored_handler(ex: PEXCEPTION_POINTERS) -> LONG {
+    if let Some(ex) = ex.as_ref() {
+        let cr = ex.ContextRecord.as_mut();
+        let er = ex.ExceptionRecord.as_ref();
+
+        if let (Some(cr), Some(er)) = (cr, er) {
+            if er.ExceptionCode == EXCEPTION_SINGLE_STEP
+                && Dispatcher::global().dispatch(&mut HwbpContext::from_context(cr))
+            {
+                return EXCEPTION_CONTINUE_EXECUTION;
+            }
+        }
+    }
+
+    EXCEPTION_CONTINUE_SEARCH
+}
@@ -1,4 +1,6 @@
-use std::convert::TryFrom;
+use std::{cmp::Ordering, convert::TryFrom, str::FromStr};
+
+use crate::{IndexOutOfRange, ParseError};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Condition {
@@ -11,6 +13,24 @@ pub enum Condition {
 }
 
 impl Condition {
+    /// Every condition paired with its two-bit representation in `CONTEXT.Dr7`.
+    pub const BITS: [(Condition, u8); 4] = [
+        (Condition::Execution, 0b00),
+        (Condition::Write, 0b01),
+        (Condition::ReadWrite, 0b11),
+        (Condition::IoReadWrite, 0b10),
+    ];
+
+    /// Returns every condition, in declaration order.
+    pub const fn all() -> [Condition; 4] {
+        [
+            Condition::Execution,
+            Condition::Write,
+            Condition::ReadWrite,
+            Condition::IoReadWrite,
+        ]
+    }
+
     pub const fn from_bits(bits: u8) -> Option<Condition> {
         match bits {
             0b00 => Some(Condition::Execution),
@@ -21,9 +41,79 @@ impl Condition {
         }
     }
 
+    /// Like [`Condition::from_bits`], but returns [`Condition::Execution`] for invalid bits
+    /// instead of `None`.
+    ///
+    /// `Execution` is what an all-zero `Dr7` decodes to, so this never panics on untrusted input.
+    pub const fn from_bits_or_default(bits: u8) -> Condition {
+        match Condition::from_bits(bits) {
+            Some(condition) => condition,
+            None => Condition::Execution,
+        }
+    }
+
     pub const fn as_bits(self) -> u8 {
         self as u8
     }
+
+    /// Returns the name of the variant, e.g. `"ReadWrite"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Condition::Execution => "Execution",
+            Condition::Write => "Write",
+            Condition::ReadWrite => "ReadWrite",
+            Condition::IoReadWrite => "IoReadWrite",
+        }
+    }
+
+    /// Returns whether this is [`Condition::Execution`].
+    pub const fn is_execution(self) -> bool {
+        matches!(self, Condition::Execution)
+    }
+
+    /// Returns whether this condition watches data accesses, i.e. [`Condition::Write`] or
+    /// [`Condition::ReadWrite`].
+    pub const fn is_data(self) -> bool {
+        matches!(self, Condition::Write | Condition::ReadWrite)
+    }
+
+    /// Returns whether this is [`Condition::IoReadWrite`].
+    pub const fn is_io(self) -> bool {
+        matches!(self, Condition::IoReadWrite)
+    }
+
+    /// Returns whether [`Condition::IoReadWrite`] breakpoints can be used.
+    ///
+    /// I/O breakpoints are only honored when `CR4.DE` is set, which can't be read or changed from
+    /// user mode. User mode code also isn't allowed to access I/O ports in the first place, so an
+    /// I/O breakpoint could never be hit by the threads this crate operates on either way.
+    ///
+    /// This therefore always returns `false`.
+    pub const fn io_breakpoints_supported() -> bool {
+        false
+    }
+
+    /// Returns whether this condition must be paired with [`Size::One`].
+    pub const fn requires_size_one(self) -> bool {
+        self.is_execution()
+    }
+}
+
+impl FromStr for Condition {
+    type Err = ParseError;
+
+    /// Parses a condition, ignoring case.
+    ///
+    /// Accepts `x`/`exec`/`execution`, `w`/`write`, `rw`/`readwrite`, and `io`/`ioreadwrite`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "x" | "exec" | "execution" => Ok(Condition::Execution),
+            "w" | "write" => Ok(Condition::Write),
+            "rw" | "readwrite" => Ok(Condition::ReadWrite),
+            "io" | "ioreadwrite" => Ok(Condition::IoReadWrite),
+            _ => Err(ParseError::InvalidCondition(s.to_owned())),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -35,6 +125,40 @@ pub enum Index {
 }
 
 impl Index {
+    /// How many indices there are, one per debug address register.
+    pub const COUNT: usize = 4;
+
+    /// Returns the name of the variant, e.g. `"First"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Index::First => "First",
+            Index::Second => "Second",
+            Index::Third => "Third",
+            Index::Fourth => "Fourth",
+        }
+    }
+
+    /// Returns the mask of this breakpoint's flag in [`Dr6`], `1 << index`.
+    ///
+    /// [`Dr6`]: crate::registers::Dr6
+    pub const fn dr6_mask(self) -> usize {
+        1 << self as usize
+    }
+
+    /// Returns the position of this breakpoint's local enable bit in [`Dr7`], `2 * index`.
+    ///
+    /// [`Dr7`]: crate::registers::Dr7
+    pub const fn dr7_local_bit(self) -> u32 {
+        2 * self as u32
+    }
+
+    /// Returns the position of this breakpoint's global enable bit in [`Dr7`], `2 * index + 1`.
+    ///
+    /// [`Dr7`]: crate::registers::Dr7
+    pub const fn dr7_global_bit(self) -> u32 {
+        2 * self as u32 + 1
+    }
+
     /// Returns the index of the breakpoint that triggered the current exception.
     ///
     /// Keep in mind that [`Dr6`] is not guaranteed to be automatically cleared, so you should clear
@@ -53,7 +177,7 @@ impl Index {
 }
 
 impl TryFrom<u8> for Index {
-    type Error = ();
+    type Error = IndexOutOfRange;
 
     fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
@@ -63,11 +187,46 @@ impl TryFrom<u8> for Index {
             x if x == Index::Second as _ => Ok(Index::Second),
             x if x == Index::Third as _ => Ok(Index::Third),
             x if x == Index::Fourth as _ => Ok(Index::Fourth),
-            _ => Err(()),
+            x => Err(IndexOutOfRange(x)),
         }
     }
 }
 
+/// A segment register, see [`HwbpContext::segment`].
+///
+/// [`HwbpContext::segment`]: crate::HwbpContext::segment
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Segment {
+    Cs,
+    Ds,
+    Es,
+    /// Points at the TEB on 32-bit Windows.
+    Fs,
+    /// Points at the TEB on 64-bit Windows.
+    Gs,
+    Ss,
+}
+
+/// What caused a debug exception, as reported by [`HwbpContext::exception_cause`].
+///
+/// Hardware breakpoints and single-stepping both raise `EXCEPTION_SINGLE_STEP`, so handlers
+/// should check this before treating an exception as a breakpoint hit.
+///
+/// [`HwbpContext::exception_cause`]: crate::HwbpContext::exception_cause
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DebugCause {
+    /// The enabled hardware breakpoint at this index was hit.
+    Breakpoint(Index),
+    /// The trap flag was set, and an instruction was executed.
+    SingleStep,
+    /// A task switch to a task with the debug trap flag set in its TSS occurred.
+    TaskSwitch,
+    /// The next instruction accesses a debug register, while general detect is enabled.
+    DebugRegisterAccess,
+    /// None of the above flags are set.
+    Unknown,
+}
+
 // Since it is not obvious which representation this enum resolves to when `as` is used to cast it,
 // we simply let it resolve to the default representation instead of picking one of the two.
 //
@@ -81,16 +240,48 @@ impl TryFrom<u8> for Index {
 /// **Avoid using `as` to cast this enum to a number, it will not return what you expect it to.**
 ///
 /// Instead, use `Size::in_bytes` and `Size::as_bits`.
+///
+/// The derived ordering follows the declaration order, which is also the order of `in_bytes`, so
+/// sorting sizes sorts them by how many bytes they cover. Keep the variants in that order.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Size {
     One,
     Two,
     Four,
     /// Eight byte breakpoints are only supported in 64-bit context.
+    ///
+    /// Prefer [`Size::eight`] when constructing breakpoints, which doesn't exist on 32-bit
+    /// targets. This variant is still available everywhere for decoding.
     Eight,
 }
 
 impl Size {
+    /// Every size paired with its two-bit representation in `CONTEXT.Dr7`.
+    pub const BITS: [(Size, u8); 4] = [
+        (Size::One, 0b00),
+        (Size::Two, 0b01),
+        (Size::Four, 0b11),
+        (Size::Eight, 0b10),
+    ];
+
+    /// Returns every size, in declaration order.
+    pub const fn all() -> [Size; 4] {
+        [Size::One, Size::Two, Size::Four, Size::Eight]
+    }
+
+    /// Returns [`Size::Eight`]. Only available on 64-bit targets, so 32-bit code can't ask for an
+    /// eight byte breakpoint through it.
+    ///
+    /// ```
+    /// # use hwbp::{Hwbp, Size};
+    /// #[cfg(target_pointer_width = "64")]
+    /// let bp = Hwbp::first().with_size(Size::eight());
+    /// ```
+    #[cfg(target_pointer_width = "64")]
+    pub const fn eight() -> Size {
+        Size::Eight
+    }
+
     /// Returns how many bytes a hardware breakpoint using this `Size` would cover.
     pub const fn in_bytes(self) -> usize {
         match self {
@@ -101,6 +292,62 @@ impl Size {
         }
     }
 
+    /// Returns the `Size` covering exactly `bytes` bytes, the inverse of [`Size::in_bytes`].
+    ///
+    /// Returns [`None`] for anything but 1, 2, 4, or 8.
+    pub const fn from_bytes(bytes: usize) -> Option<Size> {
+        match bytes {
+            1 => Some(Size::One),
+            2 => Some(Size::Two),
+            4 => Some(Size::Four),
+            8 => Some(Size::Eight),
+            _ => None,
+        }
+    }
+
+    /// Returns the conditions a breakpoint of this size can be used with.
+    ///
+    /// [`Condition::Execution`] only works with [`Size::One`], so every other size is limited to
+    /// the data conditions. This only covers the size, [`Condition::IoReadWrite`] additionally
+    /// requires [`Condition::io_breakpoints_supported`].
+    pub const fn valid_conditions(self) -> &'static [Condition] {
+        const ALL: [Condition; 4] = Condition::all();
+        const DATA: [Condition; 2] = [Condition::Write, Condition::ReadWrite];
+
+        match self {
+            Size::One => &ALL,
+            Size::Two | Size::Four | Size::Eight => &DATA,
+        }
+    }
+
+    /// Returns the name of the variant, e.g. `"Four"`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Size::One => "One",
+            Size::Two => "Two",
+            Size::Four => "Four",
+            Size::Eight => "Eight",
+        }
+    }
+
+    /// Compares two sizes by how many bytes they cover.
+    ///
+    /// This is what the derived [`Ord`] does too, but doesn't depend on the declaration order.
+    pub fn cmp_by_bytes(self, other: Size) -> Ordering {
+        self.in_bytes().cmp(&other.in_bytes())
+    }
+
+    /// Returns the largest size supported on the current architecture.
+    ///
+    /// That's [`Size::Eight`] on 64-bit, and [`Size::Four`] on 32-bit.
+    pub const fn max_for_arch() -> Size {
+        if cfg!(target_pointer_width = "64") {
+            Size::Eight
+        } else {
+            Size::Four
+        }
+    }
+
     /// Returns the two-bit representation used in `CONTEXT.Dr7`.
     pub const fn as_bits(self) -> u8 {
         match self {
@@ -121,4 +368,31 @@ impl Size {
             _ => None,
         }
     }
+
+    /// Like [`Size::from_bits`], but returns [`Size::One`] for invalid bits instead of `None`.
+    ///
+    /// `One` is what an all-zero `Dr7` decodes to, so this never panics on untrusted input.
+    pub const fn from_bits_or_default(bits: u8) -> Size {
+        match Size::from_bits(bits) {
+            Some(size) => size,
+            None => Size::One,
+        }
+    }
+}
+
+impl FromStr for Size {
+    type Err = ParseError;
+
+    /// Parses a size, ignoring case.
+    ///
+    /// Accepts a byte count (`1`, `2`, `4`, `8`), or `byte`, `word`, `dword`, and `qword`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "1" | "byte" => Ok(Size::One),
+            "2" | "word" => Ok(Size::Two),
+            "4" | "dword" => Ok(Size::Four),
+            "8" | "qword" => Ok(Size::Eight),
+            _ => Err(ParseError::InvalidSize(s.to_owned())),
+        }
+    }
 }
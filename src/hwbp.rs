@@ -1,15 +1,26 @@
-use std::{borrow::BorrowMut, ffi::c_void};
+use std::{ffi::c_void, ops::Range, ptr::NonNull};
 
-use winapi::um::winnt::CONTEXT;
+#[cfg(target_os = "windows")]
+use std::borrow::BorrowMut;
 
+#[cfg(target_os = "windows")]
+use winapi::um::winnt::{CONTEXT, HANDLE};
+
+use crate::{BreakpointError, Condition, Index, ParseError, Size};
+
+#[cfg(target_os = "windows")]
 use crate::{
-    context::{ApplyContext, FetchContext, FetchWith},
-    Condition, HwbpContext, HwbpError, Index, Size,
+    context::{self, ApplyContext, ApplyWith, FetchContext, FetchWith},
+    HwbpContext, HwbpError,
 };
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Hwbp {
+    /// Whether the local enable bit is set, which is what this crate uses to enable breakpoints.
     pub enabled: bool,
+    /// Whether the global enable bit is set, see
+    /// [`HwbpContext::set_breakpoint_global`](crate::HwbpContext::set_breakpoint_global).
+    pub global: bool,
     pub index: Index,
     pub address: *const c_void,
     pub size: Size,
@@ -18,9 +29,10 @@ pub struct Hwbp {
 
 impl Hwbp {
     #[must_use]
-    fn new() -> Self {
+    const fn new() -> Self {
         Self {
             enabled: false,
+            global: false,
             index: Index::First,
             address: std::ptr::null(),
             size: Size::One,
@@ -28,10 +40,59 @@ impl Hwbp {
         }
     }
 
-    pub fn from_index(index: Index) -> Self {
+    pub const fn from_index(index: Index) -> Self {
         Self::new().with_index(index)
     }
 
+    /// Parses a breakpoint from a spec of the form `[condition][:size]@address`, or just `address`.
+    ///
+    /// The condition and size are parsed using their [`FromStr`](std::str::FromStr) implementations,
+    /// and default to [`Condition::Execution`] and [`Size::One`] when omitted. The address is either
+    /// hexadecimal with a `0x` prefix, or decimal.
+    ///
+    /// The returned breakpoint uses [`Index::First`] and is disabled.
+    ///
+    /// ```
+    /// # use hwbp::{Condition, Hwbp, Size};
+    /// let bp = Hwbp::from_spec("rw:4@0x401000").expect("invalid spec");
+    /// assert_eq!(bp.condition, Condition::ReadWrite);
+    /// assert_eq!(bp.size, Size::Four);
+    /// assert_eq!(bp.address as usize, 0x401000);
+    ///
+    /// let bp = Hwbp::from_spec("exec@0x401000").expect("invalid spec");
+    /// assert_eq!(bp.condition, Condition::Execution);
+    /// assert_eq!(bp.size, Size::One);
+    /// ```
+    pub fn from_spec(spec: &str) -> Result<Self, ParseError> {
+        let (kind, address) = spec.rsplit_once('@').unwrap_or(("", spec));
+        let (condition, size) = kind.split_once(':').unwrap_or((kind, ""));
+
+        let condition = match condition {
+            "" => Condition::Execution,
+            condition => condition.parse()?,
+        };
+
+        let size = match size {
+            "" => Size::One,
+            size => size.parse()?,
+        };
+
+        let parsed = match address
+            .strip_prefix("0x")
+            .or_else(|| address.strip_prefix("0X"))
+        {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => address.parse(),
+        };
+
+        let address = parsed.map_err(|_| ParseError::InvalidAddress(address.to_owned()))?;
+
+        Ok(Self::new()
+            .with_condition(condition)
+            .with_size(size)
+            .with_address(address as *const ()))
+    }
+
     #[rustfmt::skip]
     multidoc! {
         /// Constructs a new hardware breakpoint.
@@ -41,6 +102,7 @@ impl Hwbp {
         /// # use hwbp::{Hwbp, Index, Size, Condition};
         /// Hwbp {
         ///     enabled: false,
+        ///     global: false,
         ///     index: ...,
         ///     address: null(),
         ///     size: Size::One,
@@ -48,45 +110,240 @@ impl Hwbp {
         /// };
         /// ```
         =>
-        pub fn first() -> Self { Self::new().with_index(Index::First) }
-        pub fn second() -> Self { Self::new().with_index(Index::Second) }
-        pub fn third() -> Self { Self::new().with_index(Index::Third) }
-        pub fn fourth() -> Self { Self::new().with_index(Index::Fourth) }
+        pub const fn first() -> Self { Self::new().with_index(Index::First) }
+        pub const fn second() -> Self { Self::new().with_index(Index::Second) }
+        pub const fn third() -> Self { Self::new().with_index(Index::Third) }
+        pub const fn fourth() -> Self { Self::new().with_index(Index::Fourth) }
     }
 }
 
+/// All builder methods are `const`, so breakpoints can be declared ahead of time:
+///
+/// ```
+/// # use hwbp::{Condition, Hwbp, Size};
+/// const BP: Hwbp = Hwbp::first()
+///     .with_size(Size::Four)
+///     .with_condition(Condition::Write);
+/// ```
 impl Hwbp {
     #[must_use]
-    pub fn with_address<T>(mut self, address: *const T) -> Hwbp {
+    pub const fn with_address<T>(mut self, address: *const T) -> Hwbp {
         self.address = address.cast();
         self
     }
 
+    /// Sets the address from a plain integer.
+    ///
+    /// ```
+    /// # use hwbp::Hwbp;
+    /// let bp = Hwbp::first().with_address_usize(0x401000);
+    /// assert_eq!(bp.address_usize(), 0x401000);
+    /// ```
+    #[must_use]
+    pub const fn with_address_usize(self, address: usize) -> Hwbp {
+        self.with_address(address as *const c_void)
+    }
+
+    /// Sets the address from a [`NonNull`] pointer.
+    ///
+    /// ```
+    /// # use hwbp::Hwbp;
+    /// # use std::ptr::NonNull;
+    /// let mut value = 0u32;
+    /// let bp = Hwbp::first().with_address_nonnull(NonNull::from(&mut value));
+    /// assert_eq!(bp.address, (&value as *const u32).cast());
+    /// ```
+    #[must_use]
+    pub const fn with_address_nonnull<T>(self, address: NonNull<T>) -> Hwbp {
+        self.with_address(address.as_ptr())
+    }
+
     #[must_use]
-    pub fn with_condition(mut self, condition: Condition) -> Hwbp {
+    pub const fn with_condition(mut self, condition: Condition) -> Hwbp {
         self.condition = condition;
         self
     }
 
     #[must_use]
-    pub fn with_size(mut self, size: Size) -> Hwbp {
+    pub const fn with_size(mut self, size: Size) -> Hwbp {
         self.size = size;
         self
     }
 
+    /// Sets both the condition and the size, rejecting combinations the processor can't honor.
+    ///
+    /// Returns [`BreakpointError::ExecutionSize`] if [`Condition::Execution`] is paired with a
+    /// size other than [`Size::One`]. The rest of [`Hwbp::validate`] depends on the address and
+    /// platform, so it isn't checked here.
+    pub const fn with_watch(
+        self,
+        condition: Condition,
+        size: Size,
+    ) -> Result<Hwbp, BreakpointError> {
+        if condition.requires_size_one() && !matches!(size, Size::One) {
+            return Err(BreakpointError::ExecutionSize);
+        }
+
+        Ok(self.with_condition(condition).with_size(size))
+    }
+
     #[must_use]
-    pub fn with_index(mut self, index: Index) -> Hwbp {
+    pub const fn with_index(mut self, index: Index) -> Hwbp {
         self.index = index;
         self
     }
 
     #[must_use]
-    pub fn with_enabled(mut self, b: bool) -> Hwbp {
+    pub const fn with_enabled(mut self, b: bool) -> Hwbp {
         self.enabled = b;
         self
     }
+
+    /// Sets whether the global enable bit is set, independently of the local one.
+    #[must_use]
+    pub const fn with_global(mut self, b: bool) -> Hwbp {
+        self.global = b;
+        self
+    }
+
+    /// Flips whether the breakpoint is enabled.
+    ///
+    /// ```
+    /// # use hwbp::{Hwbp, Size};
+    /// let bp = Hwbp::first().toggled_enabled().with_size(Size::Two);
+    /// assert!(bp.enabled());
+    /// assert!(!bp.toggled_enabled().enabled());
+    /// ```
+    #[must_use]
+    pub const fn toggled_enabled(mut self) -> Hwbp {
+        self.enabled = !self.enabled;
+        self
+    }
+
+    /// Returns whether the breakpoint is enabled.
+    #[must_use]
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns whether the local enable bit is set, the same as [`Hwbp::enabled`].
+    #[must_use]
+    pub const fn is_local_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns whether the global enable bit is set.
+    #[must_use]
+    pub const fn is_global_enabled(&self) -> bool {
+        self.global
+    }
+
+    /// Returns whether either the local or the global enable bit is set.
+    ///
+    /// This is what makes a slot count as in use everywhere in this crate. Helpers that look for
+    /// free slots skip it, and helpers that look at enabled breakpoints include it.
+    #[must_use]
+    pub const fn is_in_use(&self) -> bool {
+        self.enabled || self.global
+    }
+
+    /// Returns a copy of the breakpoint with a different index, leaving `self` untouched.
+    #[must_use]
+    pub const fn at_index(&self, index: Index) -> Hwbp {
+        self.with_index(index)
+    }
 }
 
+impl Hwbp {
+    /// Returns whether the address is aligned to the size of the breakpoint.
+    ///
+    /// Section 17.2.5 of [Intel® 64 and IA-32 Architectures Software Developer’s Manual Volume 3 (3A, 3B, 3C & 3D): System Programming Guide](https://www.intel.com/content/dam/www/public/us/en/documents/manuals/64-ia-32-architectures-software-developer-system-programming-manual-325384.pdf):
+    ///
+    /// **Breakpoint Field Recognition** — [...] Two-byte ranges must be aligned on word
+    /// boundaries; 4-byte ranges must be aligned on doubleword boundaries, 8-byte ranges must be
+    /// aligned on quadword boundaries.
+    #[must_use]
+    pub fn is_aligned(&self) -> bool {
+        self.address_usize() & (self.size.in_bytes() - 1) == 0
+    }
+
+    /// Returns the address as a `usize`, for comparing against plain integer addresses.
+    #[must_use]
+    pub fn address_usize(&self) -> usize {
+        self.address as usize
+    }
+
+    /// Reads the [`Size::in_bytes`] bytes at the address, zero-extended to a `usize`.
+    ///
+    /// This is mostly useful from a callback of a data breakpoint, to see the current value of the
+    /// watched memory. [`Size::Eight`] reads all eight bytes, but only keeps the lower four on
+    /// 32-bit targets.
+    ///
+    /// # Safety
+    /// The address has to be valid for reading [`Size::in_bytes`] bytes, and nothing may be
+    /// writing to them concurrently. It doesn't have to be aligned.
+    ///
+    /// Reading memory watched by an enabled [`Condition::ReadWrite`] breakpoint triggers it, unless
+    /// it's disarmed at the time, like it is in a `dispatcher::Dispatcher` callback by default.
+    #[must_use]
+    pub unsafe fn read_watched_value(&self) -> usize {
+        match self.size {
+            Size::One => self.address.cast::<u8>().read_unaligned() as usize,
+            Size::Two => self.address.cast::<u16>().read_unaligned() as usize,
+            Size::Four => self.address.cast::<u32>().read_unaligned() as usize,
+            Size::Eight => self.address.cast::<u64>().read_unaligned() as usize,
+        }
+    }
+
+    /// Returns whether both breakpoints have the same index, address, size, and condition.
+    ///
+    /// Unlike `==`, this ignores whether the breakpoints are enabled, locally or globally.
+    #[must_use]
+    pub fn config_eq(&self, other: &Hwbp) -> bool {
+        self.index == other.index
+            && self.address == other.address
+            && self.size == other.size
+            && self.condition == other.condition
+    }
+
+    /// Checks that the breakpoint is configured in a way the processor can honor.
+    ///
+    /// A breakpoint that fails validation can still be applied, but it will either never be hit,
+    /// or be hit for accesses other than the ones you intended.
+    pub fn validate(&self) -> Result<(), BreakpointError> {
+        if self.condition.requires_size_one() && self.size != Size::One {
+            return Err(BreakpointError::ExecutionSize);
+        }
+
+        if cfg!(target_pointer_width = "32") && self.size == Size::Eight {
+            return Err(BreakpointError::UnsupportedSize);
+        }
+
+        if !self.is_aligned() {
+            return Err(BreakpointError::Misaligned);
+        }
+
+        if self.condition.is_io() && !Condition::io_breakpoints_supported() {
+            return Err(BreakpointError::IoUnsupported);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the range of addresses the processor actually watches for this breakpoint.
+    ///
+    /// The processor ignores the low bits of the address that would make it misaligned, so a
+    /// breakpoint of size `N` always watches the `N`-aligned block containing its address, even
+    /// if that starts before the address. For aligned breakpoints, that's `address..address + N`.
+    #[must_use]
+    pub fn covered_range(&self) -> Range<usize> {
+        let size = self.size.in_bytes();
+        let start = self.address_usize() & !(size - 1);
+        start..start + size
+    }
+}
+
+#[cfg(target_os = "windows")]
 impl Hwbp {
     multidoc! {
         /// # Safety
@@ -119,9 +376,32 @@ impl Hwbp {
         }
     }
 
+    /// Applies the breakpoint to the thread behind `handle`.
+    ///
+    /// The thread is suspended while its context is fetched, modified, and applied, and resumed
+    /// afterwards. The handle needs `THREAD_GET_CONTEXT`, `THREAD_SET_CONTEXT`, and
+    /// `THREAD_SUSPEND_RESUME` access.
+    ///
+    /// # Safety
+    /// This function will never directly cause undefined behaviour, but the breakpoint it places
+    /// will for obvious reasons be a breakpoint, meaning it will cause an exception to be thrown
+    /// when it is hit. Calling this function is therefore unsafe, as it might affect the program
+    /// in unexpected ways if the caller doesn't properly set up some form of exception handling.
+    pub unsafe fn apply_to_thread(self, handle: HANDLE) -> Result<(), HwbpError> {
+        context::with_suspended(handle, || {
+            self.apply_with(
+                FetchWith::GetThreadContextOther(handle),
+                ApplyWith::SetThreadContextOther(handle),
+            )
+        })
+    }
+
     multidoc! {
         /// Enables and applies the breakpoint.
         ///
+        /// Returns [`HwbpError::UnsupportedCondition`] without touching the context if the condition
+        /// is [`Condition::IoReadWrite`], as the breakpoint would otherwise silently never trigger.
+        ///
         /// # Safety
         /// This function will never directly cause undefined behaviour, but the breakpoint it places
         /// will for obvious reasons be a breakpoint, meaning it will cause an exception to be thrown
@@ -129,6 +409,7 @@ impl Hwbp {
         /// in unexpected ways if the caller doesn't properly set up some form of exception handling.
         =>
         pub unsafe fn enable(mut self) -> Result<Hwbp, HwbpError> {
+            self.check_condition()?;
             self.enabled = true;
             let mut context = HwbpContext::get()?;
             context.set_breakpoint(self);
@@ -140,6 +421,7 @@ impl Hwbp {
             fetch: impl FetchContext,
             apply: impl ApplyContext,
         ) -> Result<Hwbp, HwbpError> {
+            self.check_condition()?;
             self.enabled = true;
             let mut context = HwbpContext::get_with(fetch)?;
             context.set_breakpoint(self);
@@ -147,6 +429,54 @@ impl Hwbp {
         }
     }
 
+    /// Enables and applies the breakpoint, returning the context that was applied.
+    ///
+    /// Useful if you want to inspect or modify the context further, without having to fetch it
+    /// again. Fails like [`Hwbp::enable`] for [`Condition::IoReadWrite`].
+    ///
+    /// # Safety
+    /// This function will never directly cause undefined behaviour, but the breakpoint it places
+    /// will for obvious reasons be a breakpoint, meaning it will cause an exception to be thrown
+    /// when it is hit. Calling this function is therefore unsafe, as it might affect the program
+    /// in unexpected ways if the caller doesn't properly set up some form of exception handling.
+    pub unsafe fn enable_into_context(mut self) -> Result<HwbpContext<CONTEXT>, HwbpError> {
+        self.check_condition()?;
+        self.enabled = true;
+        let mut context = HwbpContext::get()?;
+        context.set_breakpoint(self);
+        context.apply().map(|()| context)
+    }
+
+    multidoc! {
+        /// Enables and applies the breakpoint, then fetches the context again to verify that the
+        /// breakpoint was actually set.
+        ///
+        /// Returns [`HwbpError::VerificationFailed`] if the fetched breakpoint doesn't match.
+        ///
+        /// # Safety
+        /// This function will never directly cause undefined behaviour, but the breakpoint it places
+        /// will for obvious reasons be a breakpoint, meaning it will cause an exception to be thrown
+        /// when it is hit. Calling this function is therefore unsafe, as it might affect the program
+        /// in unexpected ways if the caller doesn't properly set up some form of exception handling.
+        =>
+        pub unsafe fn enable_verified(self) -> Result<Hwbp, HwbpError> {
+            self.enable_verified_with(FetchWith::GetThreadContext, ApplyWith::SetThreadContext)
+        }
+
+        pub unsafe fn enable_verified_with(
+            self,
+            fetch: impl FetchContext + Copy,
+            apply: impl ApplyContext,
+        ) -> Result<Hwbp, HwbpError> {
+            let hwbp = self.enable_with(fetch, apply)?;
+
+            match HwbpContext::get_with(fetch)?.breakpoint(hwbp.index) == hwbp {
+                true => Ok(hwbp),
+                false => Err(HwbpError::VerificationFailed),
+            }
+        }
+    }
+
     multidoc! {
         /// Disables and applies the breakpoint.
         ///
@@ -175,6 +505,30 @@ impl Hwbp {
         }
     }
 
+    /// Creates a breakpoint `offset` bytes into `module`, resolved with [`process::resolve`].
+    ///
+    /// Returns [`HwbpError::ModuleNotFound`] if the module isn't loaded in the current process.
+    /// Like [`Hwbp::from_spec`], the returned breakpoint uses [`Index::First`] and [`Size::One`],
+    /// and is disabled.
+    ///
+    /// ```
+    /// # use hwbp::{Condition, Hwbp};
+    /// let bp = Hwbp::at_module_offset("kernel32.dll", 0x1000, Condition::Execution)
+    ///     .expect("kernel32 isn't loaded");
+    /// ```
+    ///
+    /// [`process::resolve`]: crate::process::resolve
+    pub fn at_module_offset(
+        module: &str,
+        offset: usize,
+        condition: Condition,
+    ) -> Result<Hwbp, HwbpError> {
+        let address = crate::process::resolve(module, offset).ok_or(HwbpError::ModuleNotFound)?;
+        Ok(Self::new()
+            .with_address_usize(address)
+            .with_condition(condition))
+    }
+
     /// Returns a currently unused hardware breakpoint.
     ///
     /// ```
@@ -198,4 +552,45 @@ impl Hwbp {
     pub fn unused_with(fetch: impl FetchContext) -> Result<Option<Hwbp>, HwbpError> {
         Ok(HwbpContext::get_with(fetch)?.unused_breakpoint())
     }
+
+    /// Rejects conditions that would never trigger on this platform.
+    fn check_condition(&self) -> Result<(), HwbpError> {
+        match self.condition.is_io() && !Condition::io_breakpoints_supported() {
+            true => Err(HwbpError::UnsupportedCondition),
+            false => Ok(()),
+        }
+    }
+}
+
+/// A [`Hwbp`] that can be sent to other threads.
+///
+/// [`Hwbp`] isn't [`Send`] since it stores its address as a raw pointer. The address is never
+/// dereferenced though, it's only a value written to a debug register, so moving it to another
+/// thread is harmless.
+///
+/// ```
+/// # #[cfg(target_os = "windows")] {
+/// # use hwbp::{Hwbp, SendHwbp};
+/// let bp = SendHwbp::from(Hwbp::first());
+/// std::thread::spawn(move || unsafe { Hwbp::from(bp).apply() })
+///     .join()
+///     .unwrap()
+///     .expect("failed to apply breakpoint");
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SendHwbp(pub Hwbp);
+
+unsafe impl Send for SendHwbp {}
+
+impl From<Hwbp> for SendHwbp {
+    fn from(hwbp: Hwbp) -> Self {
+        Self(hwbp)
+    }
+}
+
+impl From<SendHwbp> for Hwbp {
+    fn from(hwbp: SendHwbp) -> Self {
+        hwbp.0
+    }
 }
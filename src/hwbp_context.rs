@@ -1,15 +1,18 @@
 use std::{
+    alloc::{alloc_zeroed, handle_alloc_error, Layout},
     borrow::{Borrow, BorrowMut},
+    cell::Cell,
     convert::TryFrom,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
 use winapi::um::winnt::{CONTEXT, CONTEXT_DEBUG_REGISTERS};
 
 use crate::{
-    context::{ApplyContext, ApplyWith, FetchContext, FetchWith},
-    raw,
-    registers::{Dr6, Dr7, EFlags},
-    Hwbp, HwbpError, Index, PseudoUsize,
+    context::{ApplyContext, ApplyWith, ContextSource, FetchContext, FetchWith},
+    dispatcher, raw,
+    registers::{Dr6, Dr6Status, Dr7, EFlags, DR7_MASK, DR7_RESERVED},
+    Breakpoints, DebugCause, Hwbp, HwbpError, Index, PseudoUsize, Segment, StaleDr6,
 };
 
 // The `align(16)` is required for [`CONTEXT`], and `winapi-rs` only left a comment reading
@@ -22,6 +25,73 @@ use crate::{
 #[repr(align(16))]
 pub struct HwbpContext<T>(T);
 
+/// Identifies a breakpoint placed with [`HwbpContext::install`], for removing it again with
+/// [`HwbpContext::remove`].
+///
+/// Every token gets a new generation, so two tokens for the same slot never compare equal, and
+/// [`HwbpContext::remove`] can tell whether the slot was reused since the token was created.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BreakpointToken {
+    hwbp: Hwbp,
+    generation: u64,
+}
+
+impl BreakpointToken {
+    /// Returns the index of the slot the breakpoint was installed in.
+    pub fn index(&self) -> Index {
+        self.hwbp.index
+    }
+
+    /// Returns the generation of the token, which is unique within the process.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the breakpoint as it was installed.
+    pub fn hwbp(&self) -> Hwbp {
+        self.hwbp
+    }
+}
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The generation of the token last installed in each slot, see [`HwbpContext::remove`].
+    static SLOT_GENERATIONS: Cell<[u64; 4]> = const { Cell::new([u64::MAX; 4]) };
+}
+
+/// Moves the slot at `index` to a generation no token has, so every token from before is stale.
+fn set_slot_generation(index: Index) -> u64 {
+    let generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+
+    SLOT_GENERATIONS.with(|slots| {
+        let mut generations = slots.get();
+        generations[index as usize] = generation;
+        slots.set(generations);
+    });
+
+    generation
+}
+
+/// An overview of which breakpoint slots are in use, see [`HwbpContext::slots`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlotSummary {
+    /// The enabled breakpoint in each slot, ordered by index.
+    pub used: [Option<Hwbp>; 4],
+    /// How many slots are free.
+    pub free: u8,
+}
+
+/// The enable bits cleared by [`HwbpContext::disable_all`], for setting them again with
+/// [`HwbpContext::enable_all_previously_set`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DisabledBreakpoints {
+    /// Whether the local enable bit was set, ordered by index.
+    pub local: [bool; 4],
+    /// Whether the global enable bit was set, ordered by index.
+    pub global: [bool; 4],
+}
+
 impl HwbpContext<CONTEXT> {
     /// Retrieves the [`HwbpContext`] for the current thread.
     pub fn get() -> Result<Self, HwbpError> {
@@ -44,6 +114,106 @@ impl HwbpContext<CONTEXT> {
         with.fetch_context(&mut context.0)?;
         Ok(context)
     }
+
+    /// Retrieves a [`HwbpContext`] from `source`, the counterpart to [`HwbpContext::apply_to`].
+    ///
+    /// ```
+    /// # use hwbp::{HwbpContext, context::{self, ContextSource, ThreadHandleSource}};
+    /// let thread: &dyn ContextSource = &ThreadHandleSource(context::current_thread());
+    /// HwbpContext::get_from(thread).expect("failed to get context");
+    /// ```
+    pub fn get_from(source: &dyn ContextSource) -> Result<Self, HwbpError> {
+        Self::get_with(source)
+    }
+
+    /// Retrieves the [`HwbpContext`] for the current thread, placed on the heap.
+    ///
+    /// `winapi-rs` doesn't declare the 16 byte alignment [`CONTEXT`] requires, so a
+    /// `Box<CONTEXT>` could end up misaligned. This boxes the aligned [`HwbpContext`] instead,
+    /// and fetches the context in place, so it never passes through the stack.
+    pub fn get_boxed() -> Result<Box<Self>, HwbpError> {
+        Self::get_boxed_with(FetchWith::GetThreadContext)
+    }
+
+    /// Retrieves a [`HwbpContext`], placed on the heap. See [`HwbpContext::get_boxed`].
+    pub fn get_boxed_with(with: impl FetchContext) -> Result<Box<Self>, HwbpError> {
+        let layout = Layout::new::<Self>();
+
+        // An all-zero `CONTEXT` is valid, and `Box` frees the allocation with the same layout
+        let mut context = unsafe {
+            let ptr = alloc_zeroed(layout) as *mut Self;
+            if ptr.is_null() {
+                handle_alloc_error(layout);
+            }
+            Box::from_raw(ptr)
+        };
+        context.0.ContextFlags = CONTEXT_DEBUG_REGISTERS;
+
+        with.fetch_context(&mut context.0)?;
+        Ok(context)
+    }
+
+    /// Copies a context out of a byte buffer, such as a thread context recovered from a minidump.
+    ///
+    /// Returns [`None`] if `bytes` is shorter than a [`CONTEXT`]. Any bytes past the end of the
+    /// [`CONTEXT`], such as extended state, are ignored. The buffer doesn't need to be aligned.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < std::mem::size_of::<CONTEXT>() {
+            return None;
+        }
+
+        let mut context: Self = unsafe { std::mem::zeroed() };
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                (&mut context.0 as *mut CONTEXT).cast::<u8>(),
+                std::mem::size_of::<CONTEXT>(),
+            );
+        }
+
+        Some(context)
+    }
+
+    /// Fetches the context of the current thread again, overwriting the stored context.
+    ///
+    /// Unlike [`HwbpContext::get`], this reuses the existing context instead of creating and
+    /// zeroing a new one, which makes it cheaper to call repeatedly.
+    pub fn refetch(&mut self) -> Result<(), HwbpError> {
+        self.refetch_with(FetchWith::GetThreadContext)
+    }
+
+    /// Fetches the context again, overwriting the stored context.
+    ///
+    /// Only `ContextFlags` is reset, to `CONTEXT_DEBUG_REGISTERS`. The rest of the context is
+    /// left as it is and overwritten by `with`.
+    ///
+    /// ```
+    /// # use hwbp::{HwbpContext, context::FetchWith};
+    /// let mut context = HwbpContext::get().expect("failed to get context");
+    /// for _ in 0..10 {
+    ///     context
+    ///         .refetch_with(FetchWith::GetThreadContext)
+    ///         .expect("failed to fetch context");
+    /// }
+    /// ```
+    pub fn refetch_with(&mut self, with: impl FetchContext) -> Result<(), HwbpError> {
+        self.0.ContextFlags = CONTEXT_DEBUG_REGISTERS;
+        with.fetch_context(&mut self.0)
+    }
+}
+
+impl HwbpContext<&mut CONTEXT> {
+    /// Wraps a context pointer, such as `ContextRecord` inside an exception handler.
+    ///
+    /// Returns [`None`] if `ptr` is null. See [`HwbpContext::from_context`] for how the wrapped
+    /// context's `ContextFlags` are treated.
+    ///
+    /// # Safety
+    /// `ptr` must either be null or valid for reads and writes for as long as the returned context
+    /// is used, and must not be accessed through any other pointer during that time.
+    pub unsafe fn from_raw(ptr: *mut CONTEXT) -> Option<Self> {
+        ptr.as_mut().map(HwbpContext)
+    }
 }
 
 impl<C: Borrow<CONTEXT>> HwbpContext<C> {
@@ -64,6 +234,9 @@ impl<C: Borrow<CONTEXT>> HwbpContext<C> {
     /// data on. The most common case would be if you're modifying the context record inside an
     /// exception handler, in which case you don't want to modify anything but the parts you're
     /// explicitly modifiying.
+    ///
+    /// If you only want the breakpoints to be applied, use
+    /// [`HwbpContext::apply_debug_registers`] instead, which sets the flags for you.
     pub fn from_context(context: C) -> HwbpContext<C> {
         HwbpContext(context)
     }
@@ -95,40 +268,211 @@ impl<C: Borrow<CONTEXT>> HwbpContext<C> {
         pub unsafe fn apply_with(&self, with: impl ApplyContext) -> Result<(), HwbpError> {
             with.apply_context(self.0.borrow())
         }
+
+        /// Applies the context to the current thread with `SetThreadContext`.
+        ///
+        /// This is what [`HwbpContext::apply`] does too, but spelled out for when the context was
+        /// fetched from another thread, or came from anywhere else, and applying it to the current
+        /// thread is intended.
+        pub unsafe fn apply_to_current(&self) -> Result<(), HwbpError> {
+            self.apply_with(ApplyWith::SetThreadContext)
+        }
+
+        /// Applies the context to `source`, the counterpart to [`HwbpContext::get_from`].
+        pub unsafe fn apply_to(&self, source: &dyn ContextSource) -> Result<(), HwbpError> {
+            self.apply_with(source)
+        }
+
+        /// Applies the context to the current thread like [`HwbpContext::apply`], and returns the
+        /// context the thread had right before, for restoring it later.
+        ///
+        /// The returned context is fetched with [`HwbpContext::get`], so it only holds the debug
+        /// registers. Restoring it with [`HwbpContext::apply`] only rolls back the breakpoints, and
+        /// leaves the rest of the thread's state alone.
+        ///
+        /// ```no_run
+        /// # unsafe {
+        /// # use hwbp::{Hwbp, HwbpContext};
+        /// let mut context = HwbpContext::get().expect("failed to get context");
+        /// context.set_breakpoint(Hwbp::first().with_enabled(true));
+        ///
+        /// let previous = context
+        ///     .apply_returning_previous()
+        ///     .expect("failed to apply context");
+        ///
+        /// // [Try the new breakpoints out here.]
+        ///
+        /// previous.apply().expect("failed to restore context");
+        /// # }
+        /// ```
+        pub unsafe fn apply_returning_previous(&self) -> Result<HwbpContext<CONTEXT>, HwbpError> {
+            let previous = HwbpContext::get()?;
+            self.apply().map(|()| previous)
+        }
     );
 
     /// Returns a currently unused hardware breakpoint, or [`None`] if all are in use.
+    ///
+    /// Slots with only the global enable bit set are in use too, see [`Hwbp::is_in_use`].
     pub fn unused_breakpoint(&self) -> Option<Hwbp> {
-        raw::get_breakpoints(self.0.borrow()).find(|bp| !bp.enabled)
+        self.find_breakpoint(|bp| !bp.is_in_use())
+    }
+
+    /// Returns the first breakpoint matching `pred`, enabled or not.
+    ///
+    /// ```
+    /// # use hwbp::HwbpContext;
+    /// let context = HwbpContext::get().expect("failed to get context");
+    /// let exec = context.find_breakpoint(|bp| bp.enabled && bp.condition.is_execution());
+    /// ```
+    pub fn find_breakpoint(&self, pred: impl Fn(&Hwbp) -> bool) -> Option<Hwbp> {
+        raw::get_breakpoints(self.0.borrow()).find(pred)
+    }
+
+    /// Returns the index of the first breakpoint matching `pred`, enabled or not.
+    pub fn position(&self, pred: impl Fn(&Hwbp) -> bool) -> Option<Index> {
+        self.find_breakpoint(pred).map(|bp| bp.index)
+    }
+
+    /// Returns whether `.ContextFlags` includes `CONTEXT_DEBUG_REGISTERS`.
+    ///
+    /// If it doesn't, the debug registers weren't filled in when the context was fetched, and
+    /// reading breakpoints from it returns garbage. This can happen when wrapping a context with
+    /// [`HwbpContext::from_context`] that was fetched with other flags.
+    ///
+    /// With the `check-context-flags` feature enabled, [`HwbpContext::breakpoint`],
+    /// [`HwbpContext::breakpoints`], [`HwbpContext::dr6`], and [`HwbpContext::dr7`] assert this
+    /// in debug builds.
+    pub fn has_debug_registers(&self) -> bool {
+        self.0.borrow().ContextFlags & CONTEXT_DEBUG_REGISTERS == CONTEXT_DEBUG_REGISTERS
+    }
+
+    fn check_flags(&self) {
+        #[cfg(feature = "check-context-flags")]
+        debug_assert!(
+            self.has_debug_registers(),
+            "ContextFlags doesn't include CONTEXT_DEBUG_REGISTERS"
+        );
     }
 
     /// Returns the breakpoint at the given index.
     pub fn breakpoint(&self, index: Index) -> Hwbp {
+        self.check_flags();
         raw::get_breakpoint(self.0.borrow(), index)
     }
 
     /// Returns all hardware breakpoints.
     pub fn breakpoints(&self) -> impl Iterator<Item = Hwbp> + '_ {
+        self.check_flags();
         raw::get_breakpoints(self.0.borrow())
     }
-}
 
-impl<C: BorrowMut<CONTEXT>> HwbpContext<C> {
-    /// Writes a breakpoint to the wrapped context.
-    pub fn set_breakpoint(&mut self, bp: Hwbp) {
-        let ctx = self.0.borrow_mut();
+    /// Returns all hardware breakpoints as a single value.
+    pub fn all(&self) -> Breakpoints {
+        Breakpoints(
+            [Index::First, Index::Second, Index::Third, Index::Fourth]
+                .map(|index| self.breakpoint(index)),
+        )
+    }
 
-        *match bp.index {
-            Index::First => &mut ctx.Dr0,
-            Index::Second => &mut ctx.Dr1,
-            Index::Third => &mut ctx.Dr2,
-            Index::Fourth => &mut ctx.Dr3,
-        } = bp.address as _;
+    /// Returns all hardware breakpoints, ordered by index.
+    ///
+    /// This is the same as [`HwbpContext::all`], as a plain array.
+    pub fn read_table(&self) -> [Hwbp; 4] {
+        self.all().0
+    }
 
-        // Set the condition, size, and enabled bits.
-        Dr7(&mut ctx.Dr7).set_size(bp.index, bp.size);
-        Dr7(&mut ctx.Dr7).set_condition(bp.index, bp.condition);
-        Dr7(&mut ctx.Dr7).set_enabled_local(bp.index, bp.enabled);
+    /// Returns which slots are used by enabled breakpoints, and how many are free.
+    pub fn slots(&self) -> SlotSummary {
+        let used = self.read_table().map(|bp| bp.is_in_use().then_some(bp));
+        let free = used.iter().filter(|bp| bp.is_none()).count() as u8;
+
+        SlotSummary { used, free }
+    }
+
+    /// Returns all enabled hardware breakpoints.
+    ///
+    /// Breakpoints with only the global enable bit set are included, see [`Hwbp::is_in_use`].
+    ///
+    /// ```
+    /// # use hwbp::HwbpContext;
+    /// let context = HwbpContext::get().expect("failed to get context");
+    ///
+    /// for bp in context.enabled_breakpoints() {
+    ///     println!("{:?} is watching {:?}", bp.index, bp.address);
+    /// }
+    /// ```
+    pub fn enabled_breakpoints(&self) -> impl Iterator<Item = Hwbp> + '_ {
+        self.breakpoints().filter(Hwbp::is_in_use)
+    }
+
+    /// Returns the indices of all enabled hardware breakpoints.
+    ///
+    /// ```
+    /// # use hwbp::HwbpContext;
+    /// let context = HwbpContext::get().expect("failed to get context");
+    /// let in_use = context.used_indices().count();
+    /// ```
+    pub fn used_indices(&self) -> impl Iterator<Item = Index> + '_ {
+        self.enabled_breakpoints().map(|bp| bp.index)
+    }
+
+    /// Returns the total number of bytes watched by enabled data breakpoints.
+    ///
+    /// Execution and I/O breakpoints don't watch memory, so they aren't counted. Overlapping
+    /// breakpoints are counted once each.
+    pub fn total_watched_bytes(&self) -> usize {
+        self.enabled_breakpoints()
+            .filter(|bp| bp.condition.is_data())
+            .map(|bp| bp.size.in_bytes())
+            .sum()
+    }
+
+    /// Returns whether any slot holds a breakpoint with the same address, size, and condition as `bp`.
+    ///
+    /// The index of `bp` is ignored. The enabled flag is only compared if `compare_enabled` is set.
+    pub fn contains(&self, bp: &Hwbp, compare_enabled: bool) -> bool {
+        self.breakpoints().any(|other| {
+            other.address == bp.address
+                && other.size == bp.size
+                && other.condition == bp.condition
+                && (!compare_enabled || other.enabled == bp.enabled)
+        })
+    }
+
+    /// Returns whether any breakpoint is enabled that isn't one of `mine`, such as one placed by
+    /// an attached debugger.
+    ///
+    /// Breakpoints are matched with [`Hwbp::config_eq`], so they must be in the same slot as when
+    /// they were placed. Slots with only the global enable bit set count as enabled too.
+    ///
+    /// This is only a heuristic. Windows clears the debug registers of a context when a debugger
+    /// asks it to, and a debugger doesn't need hardware breakpoints in the first place.
+    pub fn has_foreign_breakpoints(&self, mine: &[Hwbp]) -> bool {
+        self.breakpoints()
+            .filter(Hwbp::is_in_use)
+            .any(|bp| !mine.iter().any(|own| own.config_eq(&bp)))
+    }
+
+    /// Returns the first enabled breakpoint placed at exactly `address`.
+    pub fn find<T>(&self, address: *const T) -> Option<Hwbp> {
+        self.enabled_breakpoints()
+            .find(|bp| bp.address == address.cast())
+    }
+
+    /// Returns whether every breakpoint in `expected` is still present and unchanged.
+    ///
+    /// Each breakpoint is compared against the slot of its own `index`.
+    pub fn matches(&self, expected: &[Hwbp]) -> bool {
+        self.tampered(expected).next().is_none()
+    }
+
+    /// Returns the indices of the breakpoints in `expected` that differ from their slot.
+    pub fn tampered<'a>(&'a self, expected: &'a [Hwbp]) -> impl Iterator<Item = Index> + 'a {
+        expected
+            .iter()
+            .filter(move |bp| self.breakpoint(bp.index) != **bp)
+            .map(|bp| bp.index)
     }
 
     /// Returns breakpoints that overlap with the specified address.
@@ -153,8 +497,8 @@ impl<C: BorrowMut<CONTEXT>> HwbpContext<C> {
         /// [`Dr6`] register was not reset after a previous hwbp hit.
         =>
         pub fn breakpoints_by_dr6_value(&self, dr6: PseudoUsize) -> impl Iterator<Item = Hwbp> + '_  {
-            Dr6(dr6).breakpoints().into_iter().enumerate().filter_map(move |(i, x)| {
-                x.then(|| self.breakpoint(Index::try_from(i as u8).expect("can't fail")))
+            Dr6(dr6).breakpoints().into_iter().enumerate().filter(|(_, x)| *x).map(move |(i, _)| {
+                self.breakpoint(Index::try_from(i as u8).expect("can't fail"))
             })
         }
 
@@ -163,8 +507,360 @@ impl<C: BorrowMut<CONTEXT>> HwbpContext<C> {
         }
     }
 
+    /// Returns the first of `installed` that triggered the exception, if any.
+    ///
+    /// A breakpoint counts as triggered if its [`Dr6`] flag is set, and the slot it was placed in
+    /// still holds it, compared with [`Hwbp::config_eq`]. That way breakpoints placed by someone
+    /// else, or placed in the same slot after `installed` was, are never mistaken for the
+    /// caller's.
+    pub fn which_fired(&self, installed: &[Hwbp]) -> Option<Hwbp> {
+        self.breakpoints_by_dr6().find_map(|triggered| {
+            installed
+                .iter()
+                .find(|bp| bp.config_eq(&triggered))
+                .copied()
+        })
+    }
+
+    /// Returns whether the execution breakpoint at `index` is what triggered the exception.
+    ///
+    /// Execution breakpoints trigger before the instruction runs, so besides the [`Dr6`] flag being
+    /// set, the instruction pointer has to be the breakpoint's address. A flag left over from an
+    /// earlier hit, or set for a data breakpoint in the same slot, is therefore not attributed to
+    /// it.
+    pub fn confirm_execution_breakpoint(&self, index: Index) -> bool {
+        let bp = self.breakpoint(index);
+
+        self.dr6().breakpoint_at(index)
+            && bp.condition.is_execution()
+            && self.instruction_pointer() == bp.address_usize()
+    }
+
+    /// Returns the index of the breakpoint that triggered the exception, if exactly one
+    /// [`Dr6`] flag is set.
+    ///
+    /// As [`Dr6`] isn't reset automatically, [`StaleDr6::Multiple`] is a sign that the handler
+    /// should reset it, see [`Dr6::reset`].
+    pub fn triggered_or_stale(&self) -> Result<Index, StaleDr6> {
+        let mut triggered = [Index::First, Index::Second, Index::Third, Index::Fourth]
+            .into_iter()
+            .filter(|&index| self.dr6().breakpoint_at(index));
+
+        match (triggered.next(), triggered.next()) {
+            (Some(index), None) => Ok(index),
+            (None, _) => Err(StaleDr6::Empty),
+            (Some(_), Some(_)) => Err(StaleDr6::Multiple),
+        }
+    }
+
+    /// Like [`HwbpContext::breakpoints_by_dr6`], but yields the index of each set [`Dr6`] flag
+    /// together with the breakpoint it belongs to.
+    ///
+    /// Every set flag is yielded, so all breakpoints that triggered on the same instruction are
+    /// reported.
+    pub fn breakpoints_by_dr6_indexed(&self) -> impl Iterator<Item = (Index, Hwbp)> + '_ {
+        self.breakpoints_by_dr6().map(|bp| (bp.index, bp))
+    }
+
+    /// Returns all flags of [`Dr6`] at once.
+    pub fn dr6_status(&self) -> Dr6Status {
+        self.dr6().status()
+    }
+
+    /// Returns a copy of [`Dr6`], which only needs a shared reference to the context.
+    ///
+    /// Use [`HwbpContext::dr6_mut`] to modify it instead.
+    pub fn dr6(&self) -> Dr6<PseudoUsize> {
+        self.check_flags();
+        Dr6(self.0.borrow().Dr6)
+    }
+
+    /// Returns a copy of [`Dr7`], which only needs a shared reference to the context.
+    ///
+    /// Use [`HwbpContext::dr7_mut`] to modify it instead.
+    pub fn dr7(&self) -> Dr7<PseudoUsize> {
+        self.check_flags();
+        Dr7(self.0.borrow().Dr7)
+    }
+
+    pub fn eflags(&self) -> EFlags<u32> {
+        EFlags(self.0.borrow().EFlags)
+    }
+
+    /// Returns what caused the debug exception, based on [`Dr6`].
+    ///
+    /// Several causes can be flagged at once, in which case the first one in this order is
+    /// returned: [`DebugCause::DebugRegisterAccess`], [`DebugCause::Breakpoint`] (lowest index
+    /// first), [`DebugCause::SingleStep`], and [`DebugCause::TaskSwitch`]. Inspect [`Dr6`] directly
+    /// if you need all of them.
+    ///
+    /// Breakpoint flags are only considered for breakpoints that are enabled, as the processor
+    /// may also set them for disabled breakpoints whose condition was met.
+    pub fn exception_cause(&self) -> DebugCause {
+        let dr6 = self.dr6();
+        let dr7 = self.dr7();
+
+        if dr6.debug_register_access() {
+            return DebugCause::DebugRegisterAccess;
+        }
+
+        let hit = [Index::First, Index::Second, Index::Third, Index::Fourth]
+            .into_iter()
+            .find(|&index| {
+                dr6.breakpoint_at(index) && (dr7.enabled_local(index) || dr7.enabled_global(index))
+            });
+
+        match hit {
+            Some(index) => DebugCause::Breakpoint(index),
+            None if dr6.single_step() => DebugCause::SingleStep,
+            None if dr6.task_switch() => DebugCause::TaskSwitch,
+            None => DebugCause::Unknown,
+        }
+    }
+
+    /// Returns the instruction pointer, `Rip` on 64-bit and `Eip` on 32-bit.
+    ///
+    /// This is only filled in if the context was fetched with `CONTEXT_CONTROL`, which is the
+    /// case for exception context records, but not for [`HwbpContext::get`].
+    pub fn instruction_pointer(&self) -> usize {
+        #[cfg(target_arch = "x86_64")]
+        return self.0.borrow().Rip as usize;
+        #[cfg(target_arch = "x86")]
+        return self.0.borrow().Eip as usize;
+    }
+
+    /// Returns the stack pointer, `Rsp` on 64-bit and `Esp` on 32-bit.
+    ///
+    /// Like [`HwbpContext::instruction_pointer`], this is only filled in if the context was
+    /// fetched with `CONTEXT_CONTROL`.
+    pub fn stack_pointer(&self) -> usize {
+        #[cfg(target_arch = "x86_64")]
+        return self.0.borrow().Rsp as usize;
+        #[cfg(target_arch = "x86")]
+        return self.0.borrow().Esp as usize;
+    }
+
+    /// Returns the selector in a segment register.
+    ///
+    /// Segment registers are only filled in if the context was fetched with `CONTEXT_SEGMENTS`,
+    /// or `CONTEXT_CONTROL` for `Cs` and `Ss`.
+    pub fn segment(&self, segment: Segment) -> u16 {
+        let context = self.0.borrow();
+
+        // The fields are a WORD on 64-bit and a DWORD on 32-bit, but selectors are 16 bits
+        #[allow(clippy::unnecessary_cast)]
+        let selector = match segment {
+            Segment::Cs => context.SegCs,
+            Segment::Ds => context.SegDs,
+            Segment::Es => context.SegEs,
+            Segment::Fs => context.SegFs,
+            Segment::Gs => context.SegGs,
+            Segment::Ss => context.SegSs,
+        } as u16;
+
+        selector
+    }
+
+    /// Reads the return address at the top of the stack.
+    ///
+    /// This is only the return address when the context was captured on the first instruction of
+    /// a function, e.g. by an execution breakpoint placed on it. Anywhere else, it's just whatever
+    /// value the stack pointer points at.
+    ///
+    /// # Safety
+    /// [`HwbpContext::stack_pointer`] must point at readable memory, which is the case for a
+    /// context captured from a live thread, but not for a zeroed or deserialized context.
+    pub unsafe fn return_address(&self) -> usize {
+        (self.stack_pointer() as *const usize).read_unaligned()
+    }
+
+    /// Decodes the instruction at [`HwbpContext::instruction_pointer`].
+    ///
+    /// Returns [`None`] if the instruction pointer is null or the bytes don't form a valid
+    /// instruction. Only available with the `iced` feature enabled.
+    ///
+    /// # Safety
+    /// The 15 bytes (the maximum length of an instruction) starting at the instruction pointer
+    /// must be readable.
+    #[cfg(feature = "iced")]
+    pub unsafe fn decode_instruction(&self) -> Option<iced_x86::Instruction> {
+        use iced_x86::{Decoder, DecoderOptions};
+
+        let ip = self.instruction_pointer();
+        if ip == 0 {
+            return None;
+        }
+
+        let bytes = std::slice::from_raw_parts(ip as *const u8, 15);
+        let instruction =
+            Decoder::with_ip(usize::BITS, bytes, ip as u64, DecoderOptions::NONE).decode();
+
+        (!instruction.is_invalid()).then_some(instruction)
+    }
+}
+
+impl<C: BorrowMut<CONTEXT>> HwbpContext<C> {
+    multidoc!(
+        /// Sets `.ContextFlags = CONTEXT_DEBUG_REGISTERS` and applies the context.
+        ///
+        /// Unlike [`HwbpContext::apply`], this only ever writes the debug registers, no matter
+        /// which flags the wrapped context was created with. See [`HwbpContext::from_context`].
+        ///
+        /// # Safety
+        /// This function will never directly cause undefined behaviour, but the breakpoints it can be
+        /// used to place will cause exceptions to be thrown when they are hit. Calling this function
+        /// is therefore unsafe, as it might affect the program in unexpected ways if the caller doesn't
+        /// properly set up some form of exception handling.
+        =>
+        pub unsafe fn apply_debug_registers(&mut self) -> Result<(), HwbpError> {
+            self.apply_debug_registers_with(ApplyWith::SetThreadContext)
+        }
+
+        pub unsafe fn apply_debug_registers_with(
+            &mut self,
+            with: impl ApplyContext,
+        ) -> Result<(), HwbpError> {
+            self.0.borrow_mut().ContextFlags = CONTEXT_DEBUG_REGISTERS;
+            self.apply_with(with)
+        }
+    );
+
+    /// Like [`HwbpContext::apply_debug_registers`], but restores `.ContextFlags` afterwards, so a
+    /// context passed to an exception handler can still be resumed with.
+    pub(crate) unsafe fn apply_debug_registers_only(&mut self) -> Result<(), HwbpError> {
+        let flags = self.0.borrow().ContextFlags;
+        let result = self.apply_debug_registers();
+        self.0.borrow_mut().ContextFlags = flags;
+        result
+    }
+
+    /// Writes a breakpoint to the wrapped context, including both its local and global enable bits.
+    pub fn set_breakpoint(&mut self, bp: Hwbp) {
+        let ctx = self.0.borrow_mut();
+
+        *match bp.index {
+            Index::First => &mut ctx.Dr0,
+            Index::Second => &mut ctx.Dr1,
+            Index::Third => &mut ctx.Dr2,
+            Index::Fourth => &mut ctx.Dr3,
+        } = bp.address_usize() as PseudoUsize;
+
+        // Set the condition, size, and enabled bits.
+        Dr7(&mut ctx.Dr7).set_size(bp.index, bp.size);
+        Dr7(&mut ctx.Dr7).set_condition(bp.index, bp.condition);
+        Dr7(&mut ctx.Dr7).set_enabled_local(bp.index, bp.enabled);
+        Dr7(&mut ctx.Dr7).set_enabled_global(bp.index, bp.global);
+    }
+
+    /// Returns an unused breakpoint like [`HwbpContext::unused_breakpoint`], but also marks its
+    /// slot as enabled, so the next call returns a different one.
+    ///
+    /// The returned breakpoint is enabled, and meant to be configured and then written back with
+    /// [`HwbpContext::set_breakpoint`]. Until then, the slot is enabled with whatever it held
+    /// before, so don't apply the context in between.
+    ///
+    /// ```
+    /// # #[cfg(target_os = "windows")] {
+    /// # use hwbp::{Condition, HwbpContext};
+    /// # static FIRST: u8 = 0;
+    /// # static SECOND: u8 = 0;
+    /// let mut context = HwbpContext::get().expect("failed to get context");
+    ///
+    /// let first = context.try_reserve().expect("all breakpoints are in use");
+    /// let second = context.try_reserve().expect("all breakpoints are in use");
+    /// assert_ne!(first.index, second.index);
+    ///
+    /// context.set_breakpoint(first.with_address(&FIRST).with_condition(Condition::Write));
+    /// context.set_breakpoint(second.with_address(&SECOND).with_condition(Condition::Write));
+    /// # }
+    /// ```
+    pub fn try_reserve(&mut self) -> Option<Hwbp> {
+        let bp = self.unused_breakpoint()?.with_enabled(true);
+        self.dr7_mut().set_enabled_local(bp.index, true);
+        Some(bp)
+    }
+
+    /// Enables `bp` in an unused slot, and returns a token for removing it again, or [`None`] if
+    /// all breakpoints are in use.
+    ///
+    /// The index stored in `bp` is ignored, see [`BreakpointToken::index`] for where it ended up.
+    pub fn install(&mut self, bp: Hwbp) -> Option<BreakpointToken> {
+        let index = self.unused_breakpoint()?.index;
+        let hwbp = bp.at_index(index).with_enabled(true);
+        self.set_breakpoint(hwbp);
+
+        Some(BreakpointToken {
+            hwbp,
+            generation: set_slot_generation(index),
+        })
+    }
+
+    /// Clears the breakpoint placed by [`HwbpContext::install`], if it's still there.
+    ///
+    /// Returns `false` and leaves the slot untouched if it was cleared or reused in the meantime,
+    /// even if it was set to the exact same breakpoint again. Every slot remembers the generation
+    /// of the token last installed in it, which [`HwbpContext::clear_breakpoint`] and
+    /// [`HwbpContext::clear_breakpoints`] reset. Overwriting the slot directly, such as with
+    /// [`HwbpContext::set_breakpoint`], is only noticed if the breakpoint differs.
+    ///
+    /// The generations are tracked by the thread calling these functions, not the thread the
+    /// context belongs to. A token is always stale on a different thread than the one that
+    /// installed it.
+    pub fn remove(&mut self, token: BreakpointToken) -> bool {
+        let index = token.index();
+        let current = self.breakpoint(index);
+
+        if SLOT_GENERATIONS.with(Cell::get)[index as usize] != token.generation
+            || !current.is_in_use()
+            || !current.config_eq(&token.hwbp)
+        {
+            return false;
+        }
+
+        self.clear_breakpoint(token.index());
+        true
+    }
+
+    /// Writes a breakpoint to the slot at `index`, regardless of the index stored in `bp`.
+    ///
+    /// [`HwbpContext::set_breakpoint`] always writes to `bp.index`, this is for when the slot
+    /// should be explicit at the call site instead.
+    pub fn set_breakpoint_at(&mut self, index: Index, bp: Hwbp) {
+        self.set_breakpoint(bp.at_index(index));
+    }
+
+    /// Writes a breakpoint to the wrapped context, using the global enable bit instead of the
+    /// local one.
+    ///
+    /// Global breakpoints aren't cleared by the processor on task switches, but Windows manages
+    /// the debug registers per thread regardless, and may not honor the global bit in all
+    /// scenarios. Prefer [`HwbpContext::set_breakpoint`] unless you know you need this.
+    pub fn set_breakpoint_global(&mut self, bp: Hwbp) {
+        self.set_breakpoint(bp.with_enabled(false).with_global(bp.enabled));
+    }
+
+    /// Writes all four breakpoints to the wrapped context.
+    ///
+    /// Each breakpoint is written to the slot it's stored in, regardless of its `index`.
+    pub fn set_all(&mut self, breakpoints: Breakpoints) {
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            self.set_breakpoint(breakpoints[index].at_index(index));
+        }
+    }
+
+    /// Writes all four breakpoints to the wrapped context, ordered by index.
+    ///
+    /// This is the same as [`HwbpContext::set_all`], taking a plain array.
+    pub fn write_table(&mut self, table: [Hwbp; 4]) {
+        self.set_all(Breakpoints(table));
+    }
+
     /// Fully clears any currently set hardware breakpoints.
-    pub fn clear_breakpoints(&mut self) {
+    ///
+    /// Returns the breakpoints as they were before clearing, ordered by index, so they can be
+    /// restored later with [`HwbpContext::write_table`].
+    pub fn clear_breakpoints(&mut self) -> [Hwbp; 4] {
+        let previous = self.read_table();
         let context = self.0.borrow_mut();
 
         Dr7(&mut context.Dr7).clear_breakpoints();
@@ -172,33 +868,288 @@ impl<C: BorrowMut<CONTEXT>> HwbpContext<C> {
         context.Dr1 = 0;
         context.Dr2 = 0;
         context.Dr3 = 0;
+
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            set_slot_generation(index);
+        }
+
+        previous
     }
 
     pub fn clear_breakpoint(&mut self, index: Index) {
         Hwbp::from_index(index).with_enabled(false).apply_to(self);
+        set_slot_generation(index);
     }
 
-    pub fn dr6(&self) -> Dr6<PseudoUsize> {
-        Dr6(self.0.borrow().Dr6)
+    /// Clears every enabled breakpoint that overlaps with `address`, returning how many were
+    /// cleared.
+    ///
+    /// Breakpoints with only the global enable bit set are cleared too, see [`Hwbp::is_in_use`].
+    pub fn clear_by_address<T>(&mut self, address: *const T) -> usize {
+        let mut covering = [false; 4];
+        for bp in self.breakpoints_by_address(address).filter(Hwbp::is_in_use) {
+            covering[bp.index as usize] = true;
+        }
+
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            if covering[index as usize] {
+                self.clear_breakpoint(index);
+            }
+        }
+
+        covering.iter().filter(|&&covers| covers).count()
     }
 
-    pub fn dr7(&self) -> Dr7<PseudoUsize> {
-        Dr7(self.0.borrow().Dr7)
+    /// Disables all breakpoints, while leaving their addresses, sizes, and conditions intact.
+    ///
+    /// Returns the enable bits that were cleared. Pass them to
+    /// [`HwbpContext::enable_all_previously_set`] to enable the same breakpoints again.
+    pub fn disable_all(&mut self) -> DisabledBreakpoints {
+        let mut disabled = DisabledBreakpoints::default();
+        let mut dr7 = self.dr7_mut();
+
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            disabled.local[index as usize] = dr7.enabled_local(index);
+            disabled.global[index as usize] = dr7.enabled_global(index);
+
+            dr7.set_enabled_local(index, false);
+            dr7.set_enabled_global(index, false);
+        }
+
+        disabled
+    }
+
+    /// Disables all breakpoints, runs `f`, then restores the enable bits they had before.
+    ///
+    /// This only modifies the wrapped context, not the thread it belongs to. It's primarily meant
+    /// for exception handlers modifying their context record before returning. If `f` itself must
+    /// not trip the breakpoints, it has to apply the context it's passed, and the restored context
+    /// has to be applied again after this returns:
+    ///
+    /// ```no_run
+    /// # unsafe {
+    /// # use hwbp::HwbpContext;
+    /// # let watched = 0u8;
+    /// let mut context = HwbpContext::get().expect("failed to get context");
+    ///
+    /// let value = context.without_breakpoints(|context| {
+    ///     context.apply().expect("failed to apply context");
+    ///     std::ptr::read_volatile(&watched)
+    /// });
+    ///
+    /// context.apply().expect("failed to apply context");
+    /// # }
+    /// ```
+    pub fn without_breakpoints<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let saved = self.dr7();
+        self.disable_all();
+
+        let result = f(self);
+
+        let mut dr7 = self.dr7_mut();
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            dr7.set_enabled_local(index, saved.enabled_local(index));
+            dr7.set_enabled_global(index, saved.enabled_global(index));
+        }
+
+        result
+    }
+
+    /// Sets the global enable bit of the breakpoint at `index`, runs `f`, then restores the global
+    /// enable bit it had before.
+    ///
+    /// Like [`HwbpContext::without_breakpoints`], this only modifies the wrapped context. For the
+    /// bit to reach the thread during `f`, `f` has to apply the context it's passed, and the
+    /// restored context has to be applied again after this returns. See
+    /// [`HwbpContext::set_breakpoint_global`] for the caveats of the global bit on Windows.
+    pub fn with_global_enable<R>(&mut self, index: Index, f: impl FnOnce(&mut Self) -> R) -> R {
+        let saved = self.dr7().enabled_global(index);
+        self.dr7_mut().set_enabled_global(index, true);
+
+        let result = f(self);
+
+        self.dr7_mut().set_enabled_global(index, saved);
+        result
+    }
+
+    /// Sets the enable bits cleared by [`HwbpContext::disable_all`] again.
+    ///
+    /// Only the bits in `disabled` are set, so breakpoints that were already disabled stay
+    /// disabled, and each breakpoint keeps using the local or global bit it used before. Bits set
+    /// in the meantime are left alone.
+    pub fn enable_all_previously_set(&mut self, disabled: DisabledBreakpoints) {
+        let mut dr7 = self.dr7_mut();
+
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            if disabled.local[index as usize] {
+                dr7.set_enabled_local(index, true);
+            }
+            if disabled.global[index as usize] {
+                dr7.set_enabled_global(index, true);
+            }
+        }
+    }
+
+    /// Moves the breakpoint at `from` to `to`, and clears `from`.
+    ///
+    /// Any breakpoint previously at `to` is overwritten.
+    pub fn move_breakpoint(&mut self, from: Index, to: Index) {
+        if from != to {
+            let bp = self.breakpoint(from);
+            self.set_breakpoint(bp.at_index(to));
+            self.clear_breakpoint(from);
+        }
+    }
+
+    /// Acknowledges the breakpoints that triggered the current exception.
+    ///
+    /// This resets [`Dr6`], and if any of the triggering breakpoints is an enabled execution
+    /// breakpoint, sets the resume flag so that execution continues past it instead of immediately
+    /// hitting it again. Disabled slots flagged in [`Dr6`] are ignored, see [`Hwbp::is_in_use`].
+    ///
+    /// The exception handler still has to return `EXCEPTION_CONTINUE_EXECUTION` afterwards.
+    pub fn acknowledge(&mut self) {
+        if self
+            .breakpoints_by_dr6()
+            .any(|bp| bp.is_in_use() && bp.condition.is_execution())
+        {
+            self.eflags_mut().set_resume(true);
+        }
+
+        self.dr6_mut().reset();
+    }
+
+    /// Resets [`Dr6`] and returns which breakpoint flags were set in it, ordered by index.
+    ///
+    /// The flags are decoded from the value [`Dr6::reset`] replaced, so they always match what was
+    /// cleared, unlike reading [`HwbpContext::dr6`] and resetting it in separate steps. Only bit 16
+    /// is left set.
+    pub fn take_hits(&mut self) -> [bool; 4] {
+        Dr6(self.dr6_mut().reset()).breakpoints()
+    }
+
+    /// Returns the breakpoints that triggered the exception, clearing their [`Dr6`] flag as each
+    /// one is yielded.
+    ///
+    /// Once the iterator is exhausted, every breakpoint flag in [`Dr6`] is clear. Other bits of
+    /// [`Dr6`] are left as they are.
+    pub fn drain_triggered(&mut self) -> impl Iterator<Item = Hwbp> + '_ {
+        [Index::First, Index::Second, Index::Third, Index::Fourth]
+            .into_iter()
+            .filter_map(move |index| {
+                if !self.dr6().breakpoint_at(index) {
+                    return None;
+                }
+
+                self.dr6_mut().clear_breakpoint(index);
+                Some(self.breakpoint(index))
+            })
+    }
+
+    /// Calls `f` with each breakpoint that triggered the exception, clearing its [`Dr6`] flag,
+    /// and returns how many there were.
+    ///
+    /// This is [`HwbpContext::drain_triggered`] as a single call, for the usual handler loop.
+    ///
+    /// ```
+    /// # #[cfg(target_os = "windows")] {
+    /// # use hwbp::HwbpContext;
+    /// # let mut context = HwbpContext::get().expect("failed to get context");
+    /// let handled = context.handle_hits(|bp| {
+    ///     println!("{:?} at {:?} was hit", bp.index, bp.address);
+    /// });
+    /// # }
+    /// ```
+    pub fn handle_hits(&mut self, mut f: impl FnMut(Hwbp)) -> usize {
+        let mut handled = 0;
+        for bp in self.drain_triggered() {
+            f(bp);
+            handled += 1;
+        }
+        handled
     }
 
     pub fn dr6_mut(&mut self) -> Dr6<&mut PseudoUsize> {
         Dr6(&mut self.0.borrow_mut().Dr6)
     }
 
+    /// Writes `value` to [`Dr7`], keeping the reserved bits valid.
+    ///
+    /// Only the bits in [`DR7_MASK`] are taken from `value`. [`DR7_RESERVED`] is always set, and
+    /// every other reserved bit is always clear.
+    ///
+    /// [`DR7_MASK`]: crate::registers::DR7_MASK
+    /// [`DR7_RESERVED`]: crate::registers::DR7_RESERVED
+    pub fn set_dr7_preserving(&mut self, value: u64) {
+        self.0.borrow_mut().Dr7 = (value as PseudoUsize & DR7_MASK) | DR7_RESERVED;
+    }
+
     pub fn dr7_mut(&mut self) -> Dr7<&mut PseudoUsize> {
         Dr7(&mut self.0.borrow_mut().Dr7)
     }
 
-    pub fn eflags(&self) -> EFlags<u32> {
-        EFlags(self.0.borrow().EFlags)
+    /// Single-steps the current thread for `n` instructions once the context is applied, by setting
+    /// the trap flag. `n = 0` stops any stepping in progress.
+    ///
+    /// This is meant to be called from a [`Dispatcher`] callback, on the context of the exception,
+    /// as the count is kept for the current thread. The dispatcher then counts down each
+    /// single-step exception, calls the step callback registered with
+    /// [`Dispatcher::register_step`], and sets the trap flag again until `n` instructions were
+    /// stepped.
+    ///
+    /// [`Dispatcher`]: crate::dispatcher::Dispatcher
+    /// [`Dispatcher::register_step`]: crate::dispatcher::Dispatcher::register_step
+    pub fn step_count(&mut self, n: u32) {
+        dispatcher::set_steps_left(n);
+        self.eflags_mut().set_trap(n > 0);
+    }
+
+    /// Sets the stack pointer, `Rsp` on 64-bit and `Esp` on 32-bit.
+    ///
+    /// Only applied if the context is applied with `CONTEXT_CONTROL` set in `.ContextFlags`.
+    pub fn set_stack_pointer(&mut self, sp: usize) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.0.borrow_mut().Rsp = sp as u64;
+        }
+        #[cfg(target_arch = "x86")]
+        {
+            self.0.borrow_mut().Esp = sp as u32;
+        }
     }
 
     pub fn eflags_mut(&mut self) -> EFlags<&mut u32> {
         EFlags(&mut self.0.borrow_mut().EFlags)
     }
 }
+
+impl From<HwbpContext<CONTEXT>> for CONTEXT {
+    /// Same as [`HwbpContext::into_context`].
+    fn from(context: HwbpContext<CONTEXT>) -> Self {
+        context.into_context()
+    }
+}
+
+/// Lets a [`HwbpContext`] be passed to anything expecting a `&CONTEXT`.
+///
+/// ```
+/// # #[cfg(target_os = "windows")] {
+/// # use winapi::um::winnt::CONTEXT;
+/// # use hwbp::HwbpContext;
+/// fn debug_control(context: impl AsRef<CONTEXT>) -> u64 {
+///     context.as_ref().Dr7 as u64
+/// }
+///
+/// let context = HwbpContext::get().expect("failed to get context");
+/// let dr7 = debug_control(&context);
+///
+/// // Or take the context back out entirely.
+/// let raw: CONTEXT = context.into();
+/// assert_eq!(raw.Dr7 as u64, dr7);
+/// # }
+/// ```
+impl<C: Borrow<CONTEXT>> AsRef<CONTEXT> for HwbpContext<C> {
+    fn as_ref(&self) -> &CONTEXT {
+        self.0.borrow()
+    }
+}
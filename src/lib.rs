@@ -1,4 +1,3 @@
-#![cfg(target_os = "windows")]
 #![allow(clippy::unit_arg)]
 
 //! Hardware Breakpoints for Windows
@@ -10,6 +9,10 @@
 //!
 //! This crate is assuming that you are in user mode and not kernel mode, and all hardware breakpoints are per-thread.
 //!
+//! On other targets the crate still builds, but only contains the platform-independent types like
+//! [`Hwbp`], [`Condition`], [`Size`], [`Index`], and the error types. Everything that touches a
+//! thread context is only available on Windows.
+//!
 //! Documentation
 //! =============
 //!
@@ -20,7 +23,7 @@
 //!
 //! Using `Hwbp`:
 //! ```
-//! # unsafe {
+//! # #[cfg(target_os = "windows")] unsafe {
 //! # use hwbp::*;
 //!
 //! // Construct a `Hwbp` representing the first hwbp.
@@ -45,7 +48,7 @@
 //! instance of `HwbpContext` instead of `Hwbp`. It gives you a bit more control over the
 //! breakpoints, but it's also more verbose:
 //! ```
-//! # unsafe {
+//! # #[cfg(target_os = "windows")] unsafe {
 //! # use hwbp::*;
 //! // Get a context by calling one of these two:
 //!
@@ -72,7 +75,7 @@
 //!
 //! You'll most likely also want to handle the resulting exceptions, which you can do like this:
 //! ```
-//! # unsafe {
+//! # #[cfg(target_os = "windows")] unsafe {
 //! # use winapi::um::errhandlingapi::{AddVectoredExceptionHandler, RemoveVectoredExceptionHandler};
 //! # use winapi::um::minwinbase::EXCEPTION_SINGLE_STEP;
 //! # use winapi::um::winnt::{PEXCEPTION_POINTERS, LONG};
@@ -128,26 +131,55 @@
 //! assert_ne!(res, 0, "failed to remove exception handler");
 //! # }
 //! ```
+#[macro_use]
+mod macros;
+
+#[cfg(all(target_os = "windows", feature = "capi"))]
+pub mod capi;
+#[cfg(target_os = "windows")]
 pub mod context;
+#[cfg(target_os = "windows")]
+pub mod dispatcher;
+#[cfg(target_os = "windows")]
+pub mod process;
+#[cfg(target_os = "windows")]
 pub mod raw;
 pub mod registers;
 
-#[cfg(test)]
+#[cfg(all(test, target_os = "windows"))]
 mod tests;
 
-#[macro_use]
-mod macros;
-
+mod breakpoints;
+#[cfg(target_os = "windows")]
+mod debugger;
 mod enums;
 mod hwbp;
+#[cfg(target_os = "windows")]
 mod hwbp_context;
 
-pub use crate::enums::{Condition, Index, Size};
-pub use crate::hwbp::Hwbp;
-pub use crate::hwbp_context::HwbpContext;
+pub use crate::breakpoints::Breakpoints;
+#[cfg(target_os = "windows")]
+pub use crate::debugger::Debugger;
+pub use crate::enums::{Condition, DebugCause, Index, Segment, Size};
+pub use crate::hwbp::{Hwbp, SendHwbp};
+#[cfg(target_os = "windows")]
+pub use crate::hwbp_context::{BreakpointToken, DisabledBreakpoints, HwbpContext, SlotSummary};
 
 use std::{error::Error, fmt::Display};
 
+/// How many hardware breakpoints a thread can have at once.
+///
+/// x86 and x86-64 have four debug address registers, `Dr0` to `Dr3`.
+pub const MAX_BREAKPOINTS: usize = 4;
+
+/// Returns how many hardware breakpoints a thread can have at once.
+///
+/// This is always [`MAX_BREAKPOINTS`] for now, but other architectures differ (ARM64 can have up
+/// to 16 watchpoints), so code that doesn't need a constant should prefer this.
+pub const fn available_breakpoints() -> usize {
+    MAX_BREAKPOINTS
+}
+
 #[cfg(target_pointer_width = "64")]
 type PseudoUsize = u64;
 
@@ -158,6 +190,14 @@ type PseudoUsize = u32;
 pub enum HwbpError {
     FailedFetchContext,
     FailedApplyContext,
+    FailedSuspendThread,
+    FailedAddHandler,
+    VerificationFailed,
+    FailedSnapshot,
+    /// The breakpoint's condition can't be honored, see [`Condition::io_breakpoints_supported`].
+    UnsupportedCondition,
+    /// The module wasn't loaded in the current process, see `process::resolve`.
+    ModuleNotFound,
 }
 
 impl Error for HwbpError {}
@@ -166,6 +206,99 @@ impl Display for HwbpError {
         match self {
             Self::FailedFetchContext => write!(f, "failed to fetch thread context"),
             Self::FailedApplyContext => write!(f, "failed to apply thread context"),
+            Self::FailedSuspendThread => write!(f, "failed to suspend thread"),
+            Self::FailedAddHandler => write!(f, "failed to add exception handler"),
+            Self::VerificationFailed => write!(f, "breakpoint didn't match after applying"),
+            Self::FailedSnapshot => write!(f, "failed to snapshot threads"),
+            Self::UnsupportedCondition => write!(f, "breakpoint condition is not supported"),
+            Self::ModuleNotFound => write!(f, "module not found"),
+        }
+    }
+}
+
+/// The reason a breakpoint configuration was rejected by [`Hwbp::validate`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BreakpointError {
+    /// [`Condition::Execution`] was paired with a size other than [`Size::One`].
+    ExecutionSize,
+    /// [`Size::Eight`] was used on a 32-bit target.
+    UnsupportedSize,
+    /// The address is not aligned to the size of the breakpoint.
+    Misaligned,
+    /// [`Condition::IoReadWrite`] was used, see [`Condition::io_breakpoints_supported`].
+    IoUnsupported,
+}
+
+impl Error for BreakpointError {}
+impl Display for BreakpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ExecutionSize => write!(f, "execution breakpoints must be one byte in size"),
+            Self::UnsupportedSize => write!(f, "eight byte breakpoints require a 64-bit target"),
+            Self::Misaligned => write!(f, "breakpoint address is not aligned to its size"),
+            Self::IoUnsupported => write!(f, "I/O breakpoints are not supported in user mode"),
+        }
+    }
+}
+
+/// The error returned when converting a number outside of `0..=3` into an [`Index`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IndexOutOfRange(pub u8);
+
+impl Error for IndexOutOfRange {}
+impl Display for IndexOutOfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "debug register index {} out of range 0..=3", self.0)
+    }
+}
+
+/// The reason [`Dr6`](registers::Dr6) doesn't point at a single breakpoint, see
+/// `HwbpContext::triggered_or_stale`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StaleDr6 {
+    /// No breakpoint flags are set, so no breakpoint triggered the exception.
+    Empty,
+    /// Several breakpoint flags are set. Either several breakpoints triggered on the same
+    /// instruction, or some of the flags are left over from an earlier exception.
+    Multiple,
+}
+
+impl Error for StaleDr6 {}
+impl Display for StaleDr6 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no breakpoint flags set in Dr6"),
+            Self::Multiple => write!(f, "multiple breakpoint flags set in Dr6, possibly stale"),
+        }
+    }
+}
+
+/// The reason a string could not be parsed into a breakpoint [`Condition`], [`Size`], or a full
+/// breakpoint spec, see [`Hwbp::from_spec`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    InvalidCondition(String),
+    InvalidSize(String),
+    InvalidAddress(String),
+}
+
+impl Error for ParseError {}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCondition(s) => write!(
+                f,
+                "invalid breakpoint condition `{}`, expected x, w, rw, or io",
+                s
+            ),
+            Self::InvalidSize(s) => {
+                write!(f, "invalid breakpoint size `{}`, expected 1, 2, 4, or 8", s)
+            }
+            Self::InvalidAddress(s) => write!(
+                f,
+                "invalid breakpoint address `{}`, expected a hexadecimal (0x...) or decimal number",
+                s
+            ),
         }
     }
 }
@@ -7,3 +7,58 @@ macro_rules! multidoc {
     };
     ($(#[$meta:meta])* => ) => {}
 }
+
+/// Enables an unused hardware breakpoint watching a variable, with the size inferred from its type.
+///
+/// `watch!(w, var)` triggers on writes to `var`, and `watch!(rw, var)` on reads or writes. This
+/// expands to [`Hwbp::unused`](crate::Hwbp::unused) followed by [`Hwbp::enable`](crate::Hwbp::enable),
+/// so it has to be used in a function returning a `Result` with a [`HwbpError`](crate::HwbpError),
+/// and within an `unsafe` block.
+///
+/// # Panics
+/// Panics if no breakpoint is unused, or if the size of the variable isn't one supported by
+/// [`Size::from_bytes`](crate::Size::from_bytes).
+///
+/// # Safety
+/// See [`Hwbp::enable`](crate::Hwbp::enable).
+///
+/// ```
+/// # #[cfg(target_os = "windows")]
+/// # fn main() -> Result<(), hwbp::HwbpError> {
+/// let counter = 0u32;
+/// let hwbp = unsafe { hwbp::watch!(rw, counter)? };
+/// # unsafe { hwbp.disable()? };
+/// # Ok(())
+/// # }
+/// # #[cfg(not(target_os = "windows"))]
+/// # fn main() {}
+/// ```
+#[macro_export]
+macro_rules! watch {
+    (w, $var:expr) => {
+        $crate::watch!(@condition $crate::Condition::Write, $var)
+    };
+    (rw, $var:expr) => {
+        $crate::watch!(@condition $crate::Condition::ReadWrite, $var)
+    };
+    (@condition $condition:expr, $var:expr) => {
+        $crate::Hwbp::unused()?
+            .expect("no unused breakpoints")
+            .with_address(::core::ptr::addr_of!($var))
+            .with_size(
+                $crate::Size::from_bytes(::core::mem::size_of_val(&$var))
+                    .expect("unsupported variable size"),
+            )
+            .with_condition($condition)
+            .enable()
+    };
+}
+
+/// Emits a debug-level `log` event for a context operation, if the `log` feature is enabled.
+#[cfg(target_os = "windows")]
+macro_rules! log_op {
+    ($op:literal, $with:expr, $result:expr) => {
+        #[cfg(feature = "log")]
+        log::debug!("{} with {:?}: {:?}", $op, $with, $result);
+    };
+}
@@ -0,0 +1,262 @@
+//! Operations on every thread of a process, such as one a debugger attached to by id.
+//!
+//! Threads are found with a toolhelp snapshot, so threads created after the snapshot is taken are
+//! missed, and threads that exit before they're reached are skipped. [`install_persistent`] covers
+//! those as well, by also placing its breakpoint on every thread created later.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, TryLockError,
+};
+
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::processthreadsapi::{GetCurrentProcessId, OpenThread};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
+use winapi::um::winnt::{
+    CONTEXT, DLL_THREAD_ATTACH, HANDLE, PVOID, THREAD_GET_CONTEXT,
+    THREAD_QUERY_LIMITED_INFORMATION, THREAD_SET_CONTEXT, THREAD_SUSPEND_RESUME,
+};
+
+use crate::{
+    context::{self, ApplyWith, FetchWith},
+    Hwbp, HwbpContext, HwbpError, Index, SendHwbp,
+};
+
+/// The breakpoints placed on new threads, see [`install_persistent`].
+static PERSISTENT: Mutex<[Option<SendHwbp>; 4]> = Mutex::new([None; 4]);
+
+/// Whether [`PERSISTENT`] might hold any breakpoints, so thread creation doesn't have to lock it
+/// until something was installed.
+static ANY_PERSISTENT: AtomicBool = AtomicBool::new(false);
+
+/// Called by the loader on the new thread whenever a thread is created, before its start routine.
+///
+/// Anything in `.CRT$XLA` to `.CRT$XLZ` ends up in the TLS callback array of the image, `std` puts
+/// its own callback in `.CRT$XLB`.
+#[used]
+#[link_section = ".CRT$XLH"]
+static TLS_CALLBACK: unsafe extern "system" fn(PVOID, DWORD, PVOID) = on_thread_event;
+
+unsafe extern "system" fn on_thread_event(_: PVOID, reason: DWORD, _: PVOID) {
+    if reason != DLL_THREAD_ATTACH || !ANY_PERSISTENT.load(Ordering::Acquire) {
+        return;
+    }
+
+    // Blocking here would hold up the loader lock, and every other thread waiting on it, for as
+    // long as the mutex is held. The only holders are `install_persistent` and
+    // `uninstall_persistent`, and the breakpoints installed by the former also reach this thread
+    // through the snapshot it takes after unlocking, so it's skipped instead.
+    let persistent = match PERSISTENT.try_lock() {
+        Ok(persistent) => *persistent,
+        Err(TryLockError::Poisoned(poisoned)) => *poisoned.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+
+    // There's nothing useful to do about a failure while the loader is starting the thread
+    if let Ok(mut context) = HwbpContext::get() {
+        for bp in persistent.into_iter().flatten() {
+            context.set_breakpoint(bp.0);
+        }
+
+        let _ = context.apply();
+    }
+}
+
+/// Returns the hardware breakpoints of every thread in the process `pid`, as pairs of thread id
+/// and breakpoints ordered by index.
+///
+/// Each thread is opened with `THREAD_GET_CONTEXT`, `THREAD_SUSPEND_RESUME`, and
+/// `THREAD_QUERY_LIMITED_INFORMATION` access, and suspended while its context is fetched. Threads
+/// of another user's or an elevated process usually can't be opened with those rights without
+/// `SeDebugPrivilege`. Threads that can't be opened or fetched, whether because of missing access
+/// or because they exited in the meantime, are left out of the result.
+///
+/// The calling thread is never suspended, so `pid` may be the current process.
+///
+/// ```
+/// # use winapi::um::processthreadsapi::GetCurrentProcessId;
+/// # use hwbp::process;
+/// let threads = process::breakpoints_of_process(unsafe { GetCurrentProcessId() })
+///     .expect("failed to snapshot threads");
+///
+/// for (tid, breakpoints) in threads {
+///     let enabled = breakpoints.iter().filter(|bp| bp.is_in_use()).count();
+///     println!("thread {} has {} enabled breakpoints", tid, enabled);
+/// }
+/// ```
+pub fn breakpoints_of_process(pid: u32) -> Result<Vec<(u32, [Hwbp; 4])>, HwbpError> {
+    let access = THREAD_GET_CONTEXT | THREAD_SUSPEND_RESUME | THREAD_QUERY_LIMITED_INFORMATION;
+    let mut threads = Vec::new();
+
+    for_each_thread(pid, access, |tid, handle| {
+        let fetched = context::with_suspended(handle, || {
+            HwbpContext::get_with(FetchWith::GetThreadContextOther(handle))
+        });
+
+        if let Ok(context) = fetched {
+            threads.push((tid, context.read_table()));
+        }
+    })?;
+
+    Ok(threads)
+}
+
+/// Clears all four hardware breakpoints on every thread of the current process, and returns how
+/// many threads were cleared.
+///
+/// Each thread is suspended while its context is fetched, cleared, and applied, except for the
+/// calling thread. Threads that can't be opened or cleared, for example because they exited in the
+/// meantime, aren't counted.
+///
+/// This is meant for tearing down instrumentation, e.g. before detaching. Any exception handler
+/// that expects the breakpoints to still be there should be removed afterwards.
+pub fn clear_breakpoints_all_threads() -> Result<usize, HwbpError> {
+    // Applying is only unsafe because of the breakpoints it could place, and these contexts don't
+    // have any left
+    unsafe {
+        modify_all_threads(|context| {
+            context.clear_breakpoints();
+        })
+    }
+}
+
+/// Places `bp` on every thread of the current process, including threads created later, and
+/// returns how many existing threads it was placed on.
+///
+/// `bp` is enabled and placed in the slot of its index, replacing whatever was there before. The
+/// existing threads are modified like in [`clear_breakpoints_all_threads`]. New threads get the
+/// breakpoint from a TLS callback, which Windows calls on every new thread before its start routine
+/// runs, in both executables and DLLs. Installing another breakpoint with the same index replaces
+/// this one, and [`uninstall_persistent`] stops placing it on new threads.
+///
+/// # Limitations
+/// - The TLS callback belongs to the module this crate is linked into, so it only sees threads of
+///   the process that module is loaded in. To place breakpoints on threads of another process, the
+///   code calling this has to be a DLL loaded into that process.
+/// - Threads created with `THREAD_CREATE_FLAGS_SKIP_THREAD_ATTACH`, which is how some runtimes
+///   start their internal threads, don't run TLS callbacks and don't get the breakpoint.
+/// - The callback runs while the loader lock is held, so the breakpoint is placed with
+///   `SetThreadContext` on the new thread itself. If that fails, the thread runs without it.
+/// - To not hold up the loader lock, the callback doesn't wait while another thread is in
+///   [`install_persistent`] or [`uninstall_persistent`]. A thread created at that exact moment
+///   misses the breakpoints installed earlier, and only gets the one being installed, if any.
+/// - Anything clearing breakpoints later, like [`clear_breakpoints_all_threads`], only affects the
+///   threads that exist at that point.
+///
+/// # Safety
+/// See [`Hwbp::enable`], which applies to every thread of the process.
+pub unsafe fn install_persistent(bp: Hwbp) -> Result<usize, HwbpError> {
+    let bp = bp.with_enabled(true);
+
+    // Only referencing the callback guarantees the linker keeps it, and with it the TLS callback
+    std::hint::black_box(&TLS_CALLBACK);
+
+    // Register it before modifying the existing threads, so that a thread created in between
+    // already gets it from the callback
+    PERSISTENT.lock().unwrap_or_else(|e| e.into_inner())[bp.index as usize] = Some(bp.into());
+    ANY_PERSISTENT.store(true, Ordering::Release);
+
+    modify_all_threads(|context| context.set_breakpoint(bp))
+}
+
+/// Stops placing the breakpoint installed with [`install_persistent`] for `index` on new threads,
+/// returning it.
+///
+/// Threads that already have the breakpoint keep it, see [`clear_breakpoints_all_threads`].
+pub fn uninstall_persistent(index: Index) -> Option<Hwbp> {
+    let mut persistent = PERSISTENT.lock().unwrap_or_else(|e| e.into_inner());
+    let removed = persistent[index as usize].take();
+
+    ANY_PERSISTENT.store(persistent.iter().any(Option::is_some), Ordering::Release);
+    removed.map(Hwbp::from)
+}
+
+/// Returns the address `offset` bytes into `module`, like `module.dll+0x1234` in a debugger, or
+/// [`None`] if the module isn't loaded in the current process.
+///
+/// The module is looked up by name with `GetModuleHandleW`, which matches it case-insensitively
+/// against loaded modules and appends `.dll` if there's no extension. Its reference count isn't
+/// incremented, so the address is only valid while the module stays loaded.
+///
+/// ```
+/// # use hwbp::process;
+/// let address = process::resolve("kernel32.dll", 0x1000).expect("kernel32 isn't loaded");
+/// ```
+pub fn resolve(module: &str, offset: usize) -> Option<usize> {
+    let name: Vec<u16> = module.encode_utf16().chain(Some(0)).collect();
+    let base = unsafe { GetModuleHandleW(name.as_ptr()) };
+
+    match base.is_null() {
+        true => None,
+        false => (base as usize).checked_add(offset),
+    }
+}
+
+/// Calls `f` with the context of every thread in the current process, applies it, and returns how
+/// many threads were modified.
+///
+/// # Safety
+/// See [`HwbpContext::apply`].
+unsafe fn modify_all_threads(
+    mut f: impl FnMut(&mut HwbpContext<CONTEXT>),
+) -> Result<usize, HwbpError> {
+    let access = THREAD_GET_CONTEXT
+        | THREAD_SET_CONTEXT
+        | THREAD_SUSPEND_RESUME
+        | THREAD_QUERY_LIMITED_INFORMATION;
+    let mut modified = 0;
+
+    for_each_thread(GetCurrentProcessId(), access, |_, handle| {
+        let result = context::with_suspended(handle, || {
+            let mut context = HwbpContext::get_with(FetchWith::GetThreadContextOther(handle))?;
+            f(&mut context);
+            context.apply_with(ApplyWith::SetThreadContextOther(handle))
+        });
+
+        if result.is_ok() {
+            modified += 1;
+        }
+    })?;
+
+    Ok(modified)
+}
+
+/// Calls `f` with the id and a handle of each thread in the process `pid` that can be opened with
+/// `access`. The handle is closed once `f` returns.
+fn for_each_thread(
+    pid: u32,
+    access: DWORD,
+    mut f: impl FnMut(u32, HANDLE),
+) -> Result<(), HwbpError> {
+    unsafe {
+        // The snapshot always contains the threads of every process, `pid` only matters for
+        // snapshots of modules and heaps
+        let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(HwbpError::FailedSnapshot);
+        }
+
+        let mut entry: THREADENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as DWORD;
+
+        let mut more = Thread32First(snapshot, &mut entry);
+        while more != FALSE {
+            if entry.th32OwnerProcessID == pid {
+                let handle = OpenThread(access, FALSE, entry.th32ThreadID);
+                if !handle.is_null() {
+                    f(entry.th32ThreadID, handle);
+                    CloseHandle(handle);
+                }
+            }
+
+            more = Thread32Next(snapshot, &mut entry);
+        }
+
+        CloseHandle(snapshot);
+        Ok(())
+    }
+}
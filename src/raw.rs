@@ -1,6 +1,11 @@
+use std::ffi::c_void;
+
 use winapi::um::winnt::CONTEXT;
 
-use crate::{registers::Dr7, Hwbp, Index};
+use crate::{
+    registers::{Dr7, DR7_RESERVED},
+    Hwbp, Index, PseudoUsize,
+};
 
 /// Reads a breakpoint from the provided context.
 #[must_use]
@@ -10,11 +15,12 @@ pub fn get_breakpoint(context: &CONTEXT, index: Index) -> Hwbp {
         Index::Second => context.Dr1,
         Index::Third => context.Dr2,
         Index::Fourth => context.Dr3,
-    } as _;
+    } as usize as *const c_void;
 
     let dr7 = Dr7(context.Dr7);
     Hwbp {
         enabled: dr7.enabled_local(index),
+        global: dr7.enabled_global(index),
         index,
         address,
         size: dr7.size(index),
@@ -42,3 +48,76 @@ pub fn get_breakpoints(context: &CONTEXT) -> impl Iterator<Item = Hwbp> + '_ {
         .into_iter()
         .map(move |idx| get_breakpoint(context, idx))
 }
+
+/// Returns all breakpoints whose flag is set in the `Dr6` of the provided context.
+///
+/// This is the same as
+/// [`HwbpContext::breakpoints_by_dr6`](crate::HwbpContext::breakpoints_by_dr6), for contexts that
+/// didn't come from a live thread, e.g. ones read from a dump. Like the method, this does not
+/// check if the breakpoints are enabled or not.
+pub fn breakpoints_by_dr6(context: &CONTEXT) -> impl Iterator<Item = Hwbp> + '_ {
+    let dr6 = context.Dr6 as usize;
+    get_breakpoints(context).filter(move |bp| dr6 & bp.index.dr6_mask() != 0)
+}
+
+/// Returns the index of the first breakpoint that isn't enabled, or [`None`] if all are in use.
+///
+/// This is the same slot [`HwbpContext::unused_breakpoint`](crate::HwbpContext::unused_breakpoint)
+/// returns, without reading the rest of the breakpoint.
+#[must_use]
+pub fn first_unused(context: &CONTEXT) -> Option<Index> {
+    let mask = used_mask(context);
+    [Index::First, Index::Second, Index::Third, Index::Fourth]
+        .into_iter()
+        .find(|&index| !mask[index as usize])
+}
+
+/// Returns which breakpoints are enabled, either locally or globally, ordered by index.
+#[must_use]
+pub fn used_mask(context: &CONTEXT) -> [bool; 4] {
+    let dr7 = Dr7(context.Dr7);
+    [Index::First, Index::Second, Index::Third, Index::Fourth]
+        .map(|index| dr7.enabled_local(index) || dr7.enabled_global(index))
+}
+
+/// Composes a complete `Dr7` value from four breakpoints.
+///
+/// Each breakpoint is written to the slot of its own `index`, so this returns the same value as
+/// calling [`HwbpContext::set_breakpoint`](crate::HwbpContext::set_breakpoint) for each of them,
+/// in order, on a context with a `Dr7` of [`DR7_RESERVED`]. If several breakpoints share an
+/// index, the last one wins.
+///
+/// ```
+/// # use hwbp::{raw, Condition, Hwbp, Size};
+/// let dr7 = raw::compose_dr7([
+///     Hwbp::first().with_size(Size::Four).with_enabled(true),
+///     Hwbp::second(),
+///     Hwbp::third(),
+///     Hwbp::fourth().with_condition(Condition::Write),
+/// ]);
+/// ```
+#[must_use]
+pub const fn compose_dr7(bps: [Hwbp; 4]) -> PseudoUsize {
+    let mut dr7: PseudoUsize = DR7_RESERVED;
+
+    let mut i = 0;
+    while i < bps.len() {
+        let bp = bps[i];
+
+        let local_offset = bp.index.dr7_local_bit();
+        let global_offset = bp.index.dr7_global_bit();
+        let cond_offset = 16 + 4 * bp.index as PseudoUsize;
+        let size_offset = 18 + 4 * bp.index as PseudoUsize;
+
+        dr7 &=
+            !(1 << local_offset | 1 << global_offset | 0b11 << cond_offset | 0b11 << size_offset);
+        dr7 |= (bp.enabled as PseudoUsize) << local_offset;
+        dr7 |= (bp.global as PseudoUsize) << global_offset;
+        dr7 |= (bp.condition.as_bits() as PseudoUsize) << cond_offset;
+        dr7 |= (bp.size.as_bits() as PseudoUsize) << size_offset;
+
+        i += 1;
+    }
+
+    dr7
+}
@@ -1,4 +1,16 @@
-use crate::{Condition, Index, Size};
+use crate::{Condition, Index, PseudoUsize, Size};
+
+/// Bit 10 of [`Dr7`], which is reserved and must always be set.
+///
+/// Contexts fetched from Windows already have it set. When composing a `Dr7` value from scratch,
+/// OR this in, or use [`Dr7::with_reserved_bits`].
+pub const DR7_RESERVED: PseudoUsize = 1 << 10;
+
+/// The bits of [`Dr7`] that aren't reserved.
+///
+/// These are the enable bits 0-9, RTM (bit 11), general detect (bit 13), and the condition and
+/// size fields in bits 16-31. Bits 12, 14, 15, and everything above bit 31 must always be clear.
+pub const DR7_MASK: PseudoUsize = 0xFFFF_2BFF;
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct EFlags<T>(pub T);
@@ -47,14 +59,14 @@ macro_rules! impl_eflags {
 
     (@WRITE $inner_type:ty => $( $type:ty ),*) => {$(
         impl EFlags<$type> {
-            /// Sets the trap flag.
+            /// Sets or clears the trap flag.
             pub fn set_trap(&mut self, value: bool) {
-                self.write(self.read() | (value as $inner_type) << 8);
+                self.write(self.read() & !(1 << 8) | (value as $inner_type) << 8);
             }
 
-            /// Sets the resume flag.
+            /// Sets or clears the resume flag.
             pub fn set_resume(&mut self, value: bool) {
-                self.write(self.read() | (value as $inner_type) << 16);
+                self.write(self.read() & !(1 << 16) | (value as $inner_type) << 16);
             }
         }
     )*};
@@ -82,6 +94,18 @@ macro_rules! impl_dr6 {
 
     (@READ $inner_type:ty => $( $type:ty ),*) => {$(
         impl Dr6<$type> {
+            /// Returns all flags at once.
+            #[must_use]
+            pub fn status(&self) -> Dr6Status {
+                Dr6Status {
+                    triggered: self.breakpoints(),
+                    single_step: self.single_step(),
+                    task_switch: self.task_switch(),
+                    debug_register_access: self.debug_register_access(),
+                    rtm: self.rtm(),
+                }
+            }
+
             /// Returns whether the exception was caused by a hardware breakpoint.
             #[must_use]
             pub fn breakpoint(&self) -> bool {
@@ -125,6 +149,15 @@ macro_rules! impl_dr6 {
                 self.read() & 1 << 13 != 0
             }
 
+            /// Returns whether the exception was caused by the access trap armed with
+            /// [`Dr7::arm_access_trap`].
+            ///
+            /// This is the same flag as [`Dr6::debug_register_access`].
+            #[must_use]
+            pub fn was_access_trap(&self) -> bool {
+                self.debug_register_access()
+            }
+
             /// Returns whether the exception was caused by single-stepping.
             ///
             /// Page 581 of [Intel® 64 and IA-32 Architectures Software Developer’s Manual Volume 3 (3A, 3B, 3C & 3D): System Programming Guide](https://www.intel.com/content/dam/www/public/us/en/documents/manuals/64-ia-32-architectures-software-developer-system-programming-manual-325384.pdf):
@@ -149,6 +182,24 @@ macro_rules! impl_dr6 {
             pub fn task_switch(&self) -> bool {
                 self.read() & 1 << 15 != 0
             }
+
+            /// Returns whether the RTM flag is set.
+            ///
+            /// Note that this flag is active-low: it is **clear** when the exception occurred inside an
+            /// RTM region, and set for all other debug exceptions. Read it before calling [`Dr6::reset`],
+            /// as resetting will always set it.
+            ///
+            /// Page 581 of [Intel® 64 and IA-32 Architectures Software Developer’s Manual Volume 3 (3A, 3B, 3C & 3D): System Programming Guide](https://www.intel.com/content/dam/www/public/us/en/documents/manuals/64-ia-32-architectures-software-developer-system-programming-manual-325384.pdf):
+            ///
+            /// • **RTM (restricted transactional memory) flag (bit 16)** — Indicates (when **clear**) that a debug exception
+            ///   (#DB) or breakpoint exception (#BP) occurred inside an RTM region while advanced debugging of RTM trans-
+            ///   actional regions was enabled (see Section 17.3.3). This bit is set for any other debug exception (including all
+            ///   those that occur when advanced debugging of RTM transactional regions is not enabled). This bit is always 1 if
+            ///   the processor does not support RTM.
+            #[must_use]
+            pub fn rtm(&self) -> bool {
+                self.read() & 1 << 16 != 0
+            }
         }
     )*};
 
@@ -168,6 +219,26 @@ macro_rules! impl_dr6 {
                 self.write(1 << 16);
                 old
             }
+
+            /// Clears the flag of the breakpoint at `index`, leaving all other bits intact.
+            ///
+            /// Useful for acknowledging one breakpoint while leaving the others for a later pass.
+            pub fn clear_breakpoint(&mut self, index: Index) {
+                self.write(self.read() & !(1 << index as $inner_type));
+            }
+
+            /// Clears the flags of every breakpoint whose entry in `mask` is `true`, leaving all
+            /// other bits intact.
+            pub fn clear_breakpoints(&mut self, mask: [bool; 4]) {
+                for (index, clear) in [Index::First, Index::Second, Index::Third, Index::Fourth]
+                    .into_iter()
+                    .zip(mask)
+                {
+                    if clear {
+                        self.clear_breakpoint(index);
+                    }
+                }
+            }
         }
     )*};
 }
@@ -194,30 +265,73 @@ macro_rules! impl_dr7 {
 
     (@READ $inner_type:ty => $( $type:ty ),*) => {$(
         impl Dr7<$type> {
+            /// Decodes the fields of all four breakpoints at once.
+            #[must_use]
+            pub fn decode(&self) -> Dr7Fields {
+                let value = self.read();
+                let field = |offset: usize| (value >> offset & 0b11) as u8;
+
+                Dr7Fields {
+                    locals: [0, 1, 2, 3].map(|i| field(2 * i) & 0b01 != 0),
+                    globals: [0, 1, 2, 3].map(|i| field(2 * i) & 0b10 != 0),
+                    conditions: [0, 1, 2, 3].map(|i| {
+                        Condition::from_bits(field(16 + 4 * i))
+                            .expect("Can not be hit since all patterns & 0b11 are valid.")
+                    }),
+                    sizes: [0, 1, 2, 3].map(|i| {
+                        Size::from_bits(field(18 + 4 * i))
+                            .expect("Can not be hit since all patterns & 0b11 are valid.")
+                    }),
+                }
+            }
+
+            /// Returns whether the access trap is armed, see [`Dr7::arm_access_trap`].
+            #[must_use]
+            pub fn access_trap_armed(&self) -> bool {
+                self.read() & 1 << 13 != 0
+            }
+
             #[must_use]
             pub fn enabled_local(&self, index: Index) -> bool {
-                let local_offset = 2 * index as $inner_type;
+                let local_offset = index.dr7_local_bit();
                 self.read() & (1 << local_offset) != 0
             }
 
             #[must_use]
             pub fn enabled_global(&self, index: Index) -> bool {
-                let global_offset = 1 + 2 * index as $inner_type;
+                let global_offset = index.dr7_global_bit();
                 self.read() & (1 << global_offset) != 0
             }
 
             #[must_use]
             pub fn condition(&self, index: Index) -> Condition {
-                let cond_offset = 16 + 4 * index as $inner_type;
-                Condition::from_bits((self.read() >> cond_offset & 0b11) as u8)
+                self.try_condition(index)
                     .expect("Can not be hit since all patterns & 0b11 are valid.")
             }
 
             #[must_use]
             pub fn size(&self, index: Index) -> Size {
+                self.try_size(index)
+                    .expect("Can not be hit since all patterns & 0b11 are valid.")
+            }
+
+            /// Like [`Dr7::condition`], but without any path that panics, for decoding untrusted
+            /// values.
+            ///
+            /// Every two-bit pattern is a valid condition, so this never actually returns `None`.
+            #[must_use]
+            pub fn try_condition(&self, index: Index) -> Option<Condition> {
+                let cond_offset = 16 + 4 * index as $inner_type;
+                Condition::from_bits((self.read() >> cond_offset & 0b11) as u8)
+            }
+
+            /// Like [`Dr7::size`], but without any path that panics, for decoding untrusted values.
+            ///
+            /// Every two-bit pattern is a valid size, so this never actually returns `None`.
+            #[must_use]
+            pub fn try_size(&self, index: Index) -> Option<Size> {
                 let size_offset = 18 + 4 * index as $inner_type;
                 Size::from_bits((self.read() >> size_offset & 0b11) as u8)
-                    .expect("Can not be hit since all patterns & 0b11 are valid.")
             }
         }
     )*};
@@ -225,13 +339,13 @@ macro_rules! impl_dr7 {
     (@WRITE $inner_type:ty => $( $type:ty ),*) => {$(
         impl Dr7<$type> {
             pub fn set_enabled_local(&mut self, index: Index, enabled: bool) {
-                let local_offset = 2 * index as $inner_type;
+                let local_offset = index.dr7_local_bit();
                 self.write(self.read() & !(1 << local_offset));
                 self.write(self.read() | (enabled as $inner_type) << local_offset);
             }
 
             pub fn set_enabled_global(&mut self, index: Index, enabled: bool) {
-                let global_offset = 1 + 2 * index as $inner_type;
+                let global_offset = index.dr7_global_bit();
                 self.write(self.read() & !(1 << global_offset));
                 self.write(self.read() | (enabled as $inner_type) << global_offset);
             }
@@ -252,6 +366,38 @@ macro_rules! impl_dr7 {
                 self.write(self.read() & 0b00000000000000001111111100000000);
             }
 
+            /// Arms the access trap, by setting the GD flag.
+            ///
+            /// While armed, any instruction accessing a debug register raises a debug exception
+            /// before it executes, which can be detected with [`Dr6::was_access_trap`]. The processor
+            /// clears the flag when entering the exception handler, so it has to be armed again
+            /// afterwards. Windows may also refuse to apply it from user mode.
+            ///
+            /// Section 17.2.4 of [Intel® 64 and IA-32 Architectures Software Developer’s Manual Volume 3 (3A, 3B, 3C & 3D): System Programming Guide](https://www.intel.com/content/dam/www/public/us/en/documents/manuals/64-ia-32-architectures-software-developer-system-programming-manual-325384.pdf):
+            ///
+            /// **• GD (general detect enable) flag (bit 13)** — Enables (when set) debug-register protection, which causes a
+            /// debug exception to be generated prior to any MOV instruction that accesses a debug register. When such a
+            /// condition is detected, the BD flag in debug status register DR6 is set prior to generating the exception. This
+            /// condition is provided to support in-circuit emulators.
+            ///
+            /// The processor clears the GD flag upon entering to the debug exception handler, to allow the handler access to
+            /// the debug registers.
+            pub fn arm_access_trap(&mut self) {
+                self.write(self.read() | 1 << 13);
+            }
+
+            /// Disarms the access trap, see [`Dr7::arm_access_trap`].
+            pub fn disarm_access_trap(&mut self) {
+                self.write(self.read() & !(1 << 13));
+            }
+
+            /// Sets the reserved bits that must always be set, see [`DR7_RESERVED`].
+            #[must_use]
+            pub fn with_reserved_bits(mut self) -> Self {
+                self.write(self.read() | 1 << 10);
+                self
+            }
+
             pub fn clear_breakpoint(&mut self, index: Index) {
                 self.set_enabled_local(index, false);
                 self.set_enabled_global(index, false);
@@ -262,6 +408,78 @@ macro_rules! impl_dr7 {
     )*};
 }
 
+/// All flags of [`Dr6`], as returned by [`Dr6::status`].
+///
+/// See the matching getters of [`Dr6`] for what each flag means.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dr6Status {
+    /// See [`Dr6::breakpoints`].
+    pub triggered: [bool; 4],
+    /// See [`Dr6::single_step`].
+    pub single_step: bool,
+    /// See [`Dr6::task_switch`].
+    pub task_switch: bool,
+    /// See [`Dr6::debug_register_access`].
+    pub debug_register_access: bool,
+    /// See [`Dr6::rtm`].
+    pub rtm: bool,
+}
+
+/// The per-breakpoint fields of [`Dr7`], ordered by index.
+///
+/// Returned by [`Dr7::decode`], and turned back into a value by [`Dr7::from_fields`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dr7Fields {
+    pub locals: [bool; 4],
+    pub globals: [bool; 4],
+    pub conditions: [Condition; 4],
+    pub sizes: [Size; 4],
+}
+
+impl Dr7<PseudoUsize> {
+    /// Composes a `Dr7` value from the fields of all four breakpoints.
+    ///
+    /// All other bits are clear, except for [`DR7_RESERVED`].
+    #[must_use]
+    pub fn from_fields(fields: Dr7Fields) -> PseudoUsize {
+        let mut dr7 = Dr7(DR7_RESERVED);
+
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            let i = index as usize;
+            dr7.set_enabled_local(index, fields.locals[i]);
+            dr7.set_enabled_global(index, fields.globals[i]);
+            dr7.set_condition(index, fields.conditions[i]);
+            dr7.set_size(index, fields.sizes[i]);
+        }
+
+        dr7.0
+    }
+}
+
+/// Decodes a single breakpoint slot of a `Dr7` value, returning whether it's locally enabled,
+/// and its condition and size.
+///
+/// Takes a `u64` regardless of the target, so that values captured elsewhere (e.g. from a crash
+/// dump) can be decoded too.
+///
+/// ```
+/// # use hwbp::{registers, Condition, Index, Size};
+/// let dr7 = 0b1101 << 20 | 1 << 2;
+/// assert_eq!(
+///     registers::decode_slot(dr7, Index::Second),
+///     (true, Condition::Write, Size::Four)
+/// );
+/// ```
+#[must_use]
+pub fn decode_slot(dr7: u64, index: Index) -> (bool, Condition, Size) {
+    let dr7 = Dr7(dr7);
+    (
+        dr7.enabled_local(index),
+        dr7.condition(index),
+        dr7.size(index),
+    )
+}
+
 impl_eflags!(u32);
 impl_dr6!(usize, u32, u64);
 impl_dr7!(usize, u32, u64);
@@ -1,8 +1,12 @@
-use crate::{Condition, Hwbp, HwbpContext, Size};
-use std::ptr::{null_mut, read_volatile, write_volatile};
+use crate::{
+    raw,
+    registers::{Dr6, Dr7, DR7_RESERVED},
+    BreakpointError, Condition, Hwbp, HwbpContext, Index, Size,
+};
+use std::ptr::{addr_of, addr_of_mut, null_mut, read_volatile, write_volatile};
 use winapi::um::errhandlingapi::{AddVectoredExceptionHandler, RemoveVectoredExceptionHandler};
 use winapi::um::minwinbase::EXCEPTION_SINGLE_STEP;
-use winapi::um::winnt::{LONG, PEXCEPTION_POINTERS};
+use winapi::um::winnt::{CONTEXT, LONG, PEXCEPTION_POINTERS};
 use winapi::vc::excpt::{EXCEPTION_CONTINUE_EXECUTION, EXCEPTION_CONTINUE_SEARCH};
 
 static mut FLAG: [u8; 8] = [0; 8];
@@ -236,6 +240,249 @@ fn breakpoint_hits() {
             assert_eq!(FLAG_HITS, 8);
         }
 
+        // --- Test HwbpContext::disable_all / HwbpContext::enable_all_previously_set
+        {
+            // Prepare
+            FLAG_HITS = 0;
+            CLEAR_BP_ON_HIT = false;
+
+            // Prepare and set the breakpoint
+            Hwbp::first()
+                .with_size(Size::One)
+                .with_address(addr_of!(FLAG[0]))
+                .with_condition(Condition::Write)
+                .enable()
+                .expect("failed to enable 1b write breakpoint");
+
+            // Disable everything, keeping the configuration
+            let mut context = HwbpContext::get().expect("failed to get context");
+            let disabled = context.disable_all();
+            context.apply().expect("failed to apply context");
+
+            // Check that writing doesn't cause a hit
+            write_volatile(addr_of_mut!(FLAG[0]), 0);
+            assert_eq!(*addr_of!(FLAG_HITS), 0);
+
+            // Enable the same configuration again
+            let mut context = HwbpContext::get().expect("failed to get context");
+            context.enable_all_previously_set(disabled);
+            context.apply().expect("failed to apply context");
+
+            // Check that writing causes a hit again
+            write_volatile(addr_of_mut!(FLAG[0]), 0);
+            assert_eq!(*addr_of!(FLAG_HITS), 1);
+        }
+
+        // --- Test Debugger
+        {
+            use crate::Debugger;
+            use std::sync::atomic::{AtomicU32, Ordering};
+
+            static CALLBACK_HITS: AtomicU32 = AtomicU32::new(0);
+
+            fn on_write(_: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {
+                CALLBACK_HITS.fetch_add(1, Ordering::Relaxed);
+            }
+
+            // Prepare
+            FLAG_HITS = 0;
+            CLEAR_BP_ON_HIT = false;
+
+            let mut context = HwbpContext::get().expect("failed to get context");
+            context.clear_breakpoints();
+            context.apply().expect("failed to apply context");
+
+            let mut debugger = Debugger::new().expect("failed to create debugger");
+            let bp = Hwbp::fourth()
+                .with_size(Size::One)
+                .with_address(addr_of!(FLAG[0]))
+                .with_condition(Condition::Write);
+
+            let placed = debugger
+                .add(bp, on_write)
+                .expect("failed to add breakpoint")
+                .expect("no unused breakpoints");
+            assert!(placed.enabled);
+
+            // The debugger's handler runs before ours, so only the callback should see the hit
+            write_volatile(addr_of_mut!(FLAG[0]), 0);
+            assert_eq!(CALLBACK_HITS.load(Ordering::Relaxed), 1);
+            assert_eq!(*addr_of!(FLAG_HITS), 0);
+
+            // Dropping the debugger clears the breakpoint
+            drop(debugger);
+            assert!(!HwbpContext::get().unwrap().breakpoint(placed.index).enabled);
+
+            write_volatile(addr_of_mut!(FLAG[0]), 0);
+            assert_eq!(CALLBACK_HITS.load(Ordering::Relaxed), 1);
+            assert_eq!(*addr_of!(FLAG_HITS), 0);
+        }
+
+        // --- Test reading watched memory from a callback
+        {
+            use crate::Debugger;
+            use std::sync::atomic::{AtomicU32, Ordering};
+
+            static CALLBACK_HITS: AtomicU32 = AtomicU32::new(0);
+
+            fn on_access(_: &mut HwbpContext<&mut CONTEXT>, hwbp: Hwbp) {
+                CALLBACK_HITS.fetch_add(1, Ordering::Relaxed);
+
+                // Would trigger the breakpoint again, if it wasn't disarmed
+                unsafe { read_volatile(hwbp.address as *const u8) };
+            }
+
+            // Prepare
+            FLAG_HITS = 0;
+            CLEAR_BP_ON_HIT = false;
+
+            let mut debugger = Debugger::new().expect("failed to create debugger");
+            let bp = Hwbp::first()
+                .with_size(Size::One)
+                .with_address(addr_of!(FLAG[1]))
+                .with_condition(Condition::ReadWrite);
+
+            let placed = debugger
+                .add(bp, on_access)
+                .expect("failed to add breakpoint")
+                .expect("no unused breakpoints");
+
+            // The callback runs once, and the breakpoint is armed again afterwards
+            read_volatile(addr_of!(FLAG[1]));
+            assert_eq!(CALLBACK_HITS.load(Ordering::Relaxed), 1);
+            assert!(HwbpContext::get().unwrap().breakpoint(placed.index).enabled);
+
+            read_volatile(addr_of!(FLAG[1]));
+            assert_eq!(CALLBACK_HITS.load(Ordering::Relaxed), 2);
+            assert_eq!(*addr_of!(FLAG_HITS), 0);
+        }
+
+        // --- Test adding a breakpoint from a disarmed callback
+        {
+            use crate::Debugger;
+            use std::sync::atomic::{AtomicU32, Ordering};
+
+            static CALLBACK_HITS: AtomicU32 = AtomicU32::new(0);
+
+            fn add_second(context: &mut HwbpContext<&mut CONTEXT>, hwbp: Hwbp) {
+                CALLBACK_HITS.fetch_add(1, Ordering::Relaxed);
+
+                context.set_breakpoint(
+                    Hwbp::second()
+                        .with_size(Size::One)
+                        .with_address_usize(hwbp.address_usize() + 1)
+                        .with_condition(Condition::Write)
+                        .with_enabled(true),
+                );
+            }
+
+            // Prepare
+            FLAG_HITS = 0;
+            CLEAR_BP_ON_HIT = false;
+
+            let mut debugger = Debugger::new().expect("failed to create debugger");
+            let bp = Hwbp::first()
+                .with_size(Size::One)
+                .with_address(addr_of!(FLAG[1]))
+                .with_condition(Condition::ReadWrite);
+
+            let placed = debugger
+                .add(bp, add_second)
+                .expect("failed to add breakpoint")
+                .expect("no unused breakpoints");
+
+            // Both the disarmed breakpoint and the one the callback added are armed afterwards
+            read_volatile(addr_of!(FLAG[1]));
+            assert_eq!(CALLBACK_HITS.load(Ordering::Relaxed), 1);
+
+            let context = HwbpContext::get().expect("failed to get context");
+            assert!(context.breakpoint(placed.index).enabled);
+            assert!(context.breakpoint(Index::Second).enabled);
+
+            // The added breakpoint has no callback, so its hit reaches our handler
+            write_volatile(addr_of_mut!(FLAG[2]), 0);
+            assert_eq!(*addr_of!(FLAG_HITS), 1);
+
+            let mut context = HwbpContext::get().expect("failed to get context");
+            context.clear_breakpoint(Index::Second);
+            context.apply().expect("failed to apply context");
+        }
+
+        // --- Test reading the watched value from a callback
+        {
+            use crate::Debugger;
+            use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+            static WATCHED: AtomicU32 = AtomicU32::new(0);
+            static SEEN: AtomicUsize = AtomicUsize::new(0);
+
+            fn on_write(_: &mut HwbpContext<&mut CONTEXT>, hwbp: Hwbp) {
+                // Write breakpoints trigger after the write, so this is the new value
+                SEEN.store(unsafe { hwbp.read_watched_value() }, Ordering::Relaxed);
+            }
+
+            // Prepare
+            FLAG_HITS = 0;
+            CLEAR_BP_ON_HIT = false;
+
+            let mut debugger = Debugger::new().expect("failed to create debugger");
+            debugger
+                .add(
+                    Hwbp::first()
+                        .with_size(Size::Four)
+                        .with_address(WATCHED.as_ptr())
+                        .with_condition(Condition::Write),
+                    on_write,
+                )
+                .expect("failed to add breakpoint")
+                .expect("no unused breakpoints");
+
+            WATCHED.store(0xDEAD_BEEF, Ordering::Relaxed);
+            assert_eq!(SEEN.load(Ordering::Relaxed), 0xDEAD_BEEF);
+            assert_eq!(*addr_of!(FLAG_HITS), 0);
+        }
+
+        // --- Test stepping after a hit
+        {
+            use crate::dispatcher::Dispatcher;
+            use crate::Debugger;
+            use std::sync::atomic::{AtomicU32, Ordering};
+
+            static STEPS: AtomicU32 = AtomicU32::new(0);
+
+            fn on_write(context: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {
+                context.step_count(3);
+            }
+
+            fn on_step(_: &mut HwbpContext<&mut CONTEXT>, _: u32) {
+                STEPS.fetch_add(1, Ordering::Relaxed);
+            }
+
+            // Prepare
+            FLAG_HITS = 0;
+            CLEAR_BP_ON_HIT = false;
+
+            let mut debugger = Debugger::new().expect("failed to create debugger");
+            Dispatcher::global().register_step(on_step);
+
+            debugger
+                .add(
+                    Hwbp::first()
+                        .with_address(addr_of!(FLAG[2]))
+                        .with_condition(Condition::Write),
+                    on_write,
+                )
+                .expect("failed to add breakpoint")
+                .expect("no unused breakpoints");
+
+            // Exactly three instructions are stepped after the write, whatever they are
+            write_volatile(addr_of_mut!(FLAG[2]), 0);
+            assert_eq!(STEPS.load(Ordering::Relaxed), 3);
+            assert_eq!(*addr_of!(FLAG_HITS), 0);
+
+            Dispatcher::global().unregister_step();
+        }
+
         // --- --- --- --- --- TESTS END HERE
 
         // Clear any leftover breakpoints
@@ -245,3 +492,2652 @@ fn breakpoint_hits() {
         RemoveVectoredExceptionHandler(veh);
     }
 }
+
+#[test]
+fn dr6_rtm() {
+    // The RTM flag is active-low, so a reset Dr6 reports it as set
+    let mut dr6 = Dr6(0u64);
+    assert!(!dr6.rtm());
+
+    dr6.reset();
+    assert!(dr6.rtm());
+
+    // Other bits shouldn't affect it
+    assert!(Dr6(1u64 << 16 | 0b1111).rtm());
+    assert!(!Dr6(!(1u64 << 16)).rtm());
+}
+
+#[test]
+fn eflags_clear() {
+    use crate::registers::EFlags;
+
+    let mut eflags = EFlags(0u32);
+    eflags.set_trap(true);
+    eflags.set_resume(true);
+    assert!(eflags.trap() && eflags.resume());
+
+    // Passing false clears the flag, without touching the other one
+    eflags.set_trap(false);
+    assert!(!eflags.trap() && eflags.resume());
+
+    eflags.set_resume(false);
+    assert!(!eflags.trap() && !eflags.resume());
+    assert_eq!(eflags.0, 0);
+}
+
+#[cfg(feature = "capi")]
+#[test]
+fn capi_roundtrip() {
+    use crate::capi::{hwbp_clear_all, hwbp_get, hwbp_set, HwbpInfo};
+    use std::ffi::c_void;
+
+    // Call everything through function pointers, like a C caller would
+    let set: unsafe extern "C" fn(u8, *const c_void, u8, u8, bool) -> i32 = hwbp_set;
+    let clear_all: unsafe extern "C" fn() -> i32 = hwbp_clear_all;
+    let get: unsafe extern "C" fn(u8, *mut HwbpInfo) -> i32 = hwbp_get;
+
+    unsafe {
+        let address = addr_of!(FLAG).cast::<c_void>();
+        let mut info = std::mem::zeroed::<HwbpInfo>();
+
+        // Set a disabled breakpoint, so it can't be hit while testing
+        let size = Size::Four.as_bits();
+        let condition = Condition::Write.as_bits();
+        assert_eq!(set(1, address, size, condition, false), 0);
+
+        // Read it back
+        assert_eq!(get(1, &mut info), 0);
+        assert_eq!(
+            info,
+            HwbpInfo {
+                address,
+                size_bits: size,
+                condition_bits: condition,
+                enabled: false,
+            }
+        );
+
+        // Clear everything and make sure it's gone
+        assert_eq!(clear_all(), 0);
+        assert_eq!(get(1, &mut info), 0);
+        assert!(info.address.is_null());
+
+        // Invalid arguments are rejected
+        assert_ne!(set(4, address, size, condition, false), 0);
+        assert_ne!(get(0, null_mut()), 0);
+    }
+}
+
+#[test]
+fn raw_breakpoints_by_dr6() {
+    let mut record = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let mut context = HwbpContext::from_context(&mut record);
+    context.set_breakpoint(
+        Hwbp::first()
+            .with_address(0x1000 as *const ())
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    context.set_breakpoint(
+        Hwbp::fourth()
+            .with_address(0x2000 as *const ())
+            .with_size(Size::Four)
+            .with_condition(Condition::ReadWrite)
+            .with_enabled(true),
+    );
+
+    // Only the flags matter, not whether the breakpoints are enabled
+    record.Dr6 = 0b1100;
+
+    let triggered = raw::breakpoints_by_dr6(&record).collect::<Vec<_>>();
+    assert_eq!(
+        triggered.iter().map(|bp| bp.index).collect::<Vec<_>>(),
+        [Index::Third, Index::Fourth]
+    );
+    assert_eq!(triggered[1].address, 0x2000 as *const _);
+    assert_eq!(triggered[1].size, Size::Four);
+
+    let context = HwbpContext::from_context(&record);
+    assert!(triggered.into_iter().eq(context.breakpoints_by_dr6()));
+
+    record.Dr6 = 0;
+    assert_eq!(raw::breakpoints_by_dr6(&record).count(), 0);
+}
+
+#[test]
+fn compose_dr7() {
+    let bps = [
+        Hwbp::first()
+            .with_size(Size::Two)
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+        Hwbp::second()
+            .with_size(Size::One)
+            .with_condition(Condition::Execution),
+        Hwbp::third()
+            .with_size(Size::Four)
+            .with_condition(Condition::ReadWrite)
+            .with_enabled(true),
+        Hwbp::fourth()
+            .with_size(Size::Eight)
+            .with_condition(Condition::IoReadWrite)
+            .with_enabled(true),
+    ];
+
+    // Write the same breakpoints through a context
+    let mut initial = unsafe { std::mem::zeroed::<CONTEXT>() };
+    initial.Dr7 = DR7_RESERVED;
+
+    let mut context = HwbpContext::from_context(initial);
+    for bp in bps {
+        context.set_breakpoint(bp);
+    }
+
+    assert_eq!(raw::compose_dr7(bps), context.into_context().Dr7);
+}
+
+#[test]
+fn dr7_try_decode() {
+    // Every pattern of the condition and size bits of each slot, plus some noise elsewhere
+    for bits in 0..16u64 {
+        for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+            let dr7 = Dr7(bits << (16 + 4 * index as u64) | 0b1010_0101);
+
+            assert_eq!(dr7.try_condition(index), Some(dr7.condition(index)));
+            assert_eq!(dr7.try_size(index), Some(dr7.size(index)));
+            assert_eq!(
+                dr7.try_condition(index).map(Condition::as_bits),
+                Some((bits & 0b11) as u8)
+            );
+            assert_eq!(
+                dr7.try_size(index).map(Size::as_bits),
+                Some((bits >> 2) as u8)
+            );
+        }
+    }
+
+    let all_ones = Dr7(u64::MAX);
+    assert_eq!(
+        all_ones.try_condition(Index::Fourth),
+        Some(Condition::ReadWrite)
+    );
+    assert_eq!(all_ones.try_size(Index::Fourth), Some(Size::Four));
+}
+
+#[test]
+fn dr7_reserved_bit() {
+    // A freshly composed Dr7 has the reserved bit set, even with nothing enabled
+    let dr7 = raw::compose_dr7([Hwbp::first(), Hwbp::second(), Hwbp::third(), Hwbp::fourth()]);
+    assert_eq!(dr7, DR7_RESERVED);
+    assert_ne!(dr7 & 1 << 10, 0);
+
+    assert_eq!(Dr7(0u64).with_reserved_bits().0, 1 << 10);
+    assert_eq!(Dr7(0b11u64).with_reserved_bits().0, 0b11 | 1 << 10);
+}
+
+#[test]
+fn tamper_detection() {
+    let expected = [
+        Hwbp::first()
+            .with_address(0x1000 as *const ())
+            .with_enabled(true),
+        Hwbp::third()
+            .with_address(0x2000 as *const ())
+            .with_size(Size::Four)
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    ];
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    for bp in expected {
+        context.set_breakpoint(bp);
+    }
+
+    // Untouched registers match
+    assert!(context.matches(&expected));
+    assert_eq!(context.tampered(&expected).next(), None);
+
+    // Move the third breakpoint somewhere else
+    let mut context = HwbpContext::from_context(context.into_context());
+    context.set_breakpoint(expected[1].with_address(0x3000 as *const ()));
+
+    assert!(!context.matches(&expected));
+    assert_eq!(
+        context.tampered(&expected).collect::<Vec<_>>(),
+        [Index::Third]
+    );
+}
+
+#[test]
+fn condition_helpers() {
+    let exec = Condition::Execution;
+    assert!(exec.is_execution() && !exec.is_data() && !exec.is_io() && exec.requires_size_one());
+
+    let write = Condition::Write;
+    assert!(
+        !write.is_execution() && write.is_data() && !write.is_io() && !write.requires_size_one()
+    );
+
+    let rw = Condition::ReadWrite;
+    assert!(!rw.is_execution() && rw.is_data() && !rw.is_io() && !rw.requires_size_one());
+
+    let io = Condition::IoReadWrite;
+    assert!(!io.is_execution() && !io.is_data() && io.is_io() && !io.requires_size_one());
+}
+
+#[test]
+fn alignment() {
+    let data = |size, address: usize| {
+        Hwbp::first()
+            .with_condition(Condition::Write)
+            .with_size(size)
+            .with_address(address as *const ())
+    };
+
+    // An eight byte aligned address is aligned for every size
+    for size in [Size::One, Size::Two, Size::Four, Size::Eight] {
+        assert!(data(size, 0x1000).is_aligned());
+    }
+
+    // One byte breakpoints can't be misaligned
+    assert!(data(Size::One, 0x1001).is_aligned());
+
+    assert!(data(Size::Two, 0x1002).is_aligned());
+    assert!(!data(Size::Two, 0x1001).is_aligned());
+
+    assert!(data(Size::Four, 0x1004).is_aligned());
+    assert!(!data(Size::Four, 0x1002).is_aligned());
+
+    assert!(data(Size::Eight, 0x1008).is_aligned());
+    assert!(!data(Size::Eight, 0x1004).is_aligned());
+
+    // Validation rejects misaligned addresses
+    assert_eq!(data(Size::Four, 0x1004).validate(), Ok(()));
+    assert_eq!(
+        data(Size::Four, 0x1001).validate(),
+        Err(BreakpointError::Misaligned)
+    );
+}
+
+#[test]
+fn covered_range() {
+    let data = |size, address: usize| {
+        Hwbp::first()
+            .with_condition(Condition::Write)
+            .with_size(size)
+            .with_address(address as *const ())
+    };
+
+    // Aligned breakpoints cover exactly their own bytes
+    assert_eq!(data(Size::One, 0x1003).covered_range(), 0x1003..0x1004);
+    assert_eq!(data(Size::Four, 0x1004).covered_range(), 0x1004..0x1008);
+
+    // Misaligned ones cover the aligned block containing the address instead
+    assert_eq!(data(Size::Two, 0x1003).covered_range(), 0x1002..0x1004);
+    assert_eq!(data(Size::Four, 0x1006).covered_range(), 0x1004..0x1008);
+    assert_eq!(data(Size::Eight, 0x100D).covered_range(), 0x1008..0x1010);
+}
+
+#[test]
+fn move_breakpoint() {
+    let bp = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_size(Size::Four)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint(bp);
+    context.move_breakpoint(Index::Second, Index::Fourth);
+
+    // The configuration is preserved at the new index
+    assert_eq!(
+        context.breakpoint(Index::Fourth),
+        bp.at_index(Index::Fourth)
+    );
+
+    // The source is cleared
+    let source = context.breakpoint(Index::Second);
+    assert!(!source.enabled);
+    assert!(source.address.is_null());
+}
+
+#[test]
+fn dispatcher_concurrent_registration() {
+    use crate::dispatcher::Dispatcher;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static HITS: AtomicU32 = AtomicU32::new(0);
+
+    fn count(_: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn ignore(_: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {}
+
+    let indices = [Index::First, Index::Second, Index::Third, Index::Fourth];
+    let dispatcher = Dispatcher::new();
+
+    // Hammer all slots from several threads, always finishing with `count` registered
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                for i in 0..1000 {
+                    for index in indices {
+                        match i % 3 {
+                            0 => dispatcher.register(index, ignore),
+                            1 => dispatcher.unregister(index),
+                            _ => dispatcher.register(index, count),
+                        };
+                    }
+                }
+
+                for index in indices {
+                    dispatcher.register(index, count);
+                }
+            });
+        }
+    });
+
+    // Pretend all four breakpoints were hit at once
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    raw.Dr6 = 0b1111;
+
+    let mut context = HwbpContext::from_context(&mut raw);
+    assert!(dispatcher.dispatch(&mut context));
+
+    // Every callback ran, and Dr6 was reset
+    assert_eq!(HITS.load(Ordering::Relaxed), 4);
+    assert!(!context.dr6().breakpoint());
+
+    // Nothing is handled without callbacks
+    for index in indices {
+        dispatcher.unregister(index);
+    }
+
+    raw.Dr6 = 0b0001;
+    assert!(!dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw)));
+    assert_eq!(raw.Dr6, 0b0001);
+}
+
+#[test]
+fn read_only_context() {
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+
+    HwbpContext::from_context(&mut raw).set_breakpoint(
+        Hwbp::third()
+            .with_address(0x1000 as *const ())
+            .with_size(Size::Four)
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    raw.Dr6 = 0b0100;
+
+    // Everything below only needs a shared reference
+    let context = HwbpContext::from_context(&raw);
+
+    let by_address = context.breakpoints_by_address(0x1002 as *const ());
+    assert_eq!(
+        by_address.map(|bp| bp.index).collect::<Vec<_>>(),
+        [Index::Third]
+    );
+
+    let by_dr6 = context.breakpoints_by_dr6();
+    assert_eq!(
+        by_dr6.map(|bp| bp.index).collect::<Vec<_>>(),
+        [Index::Third]
+    );
+
+    assert!(context.dr6().breakpoint_at(Index::Third));
+    assert!(context.dr7().enabled_local(Index::Third));
+    assert_eq!(context.dr7().size(Index::Third), Size::Four);
+}
+
+#[test]
+fn slots() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let first = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_size(Size::Four)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    let third = Hwbp::third()
+        .with_address(0x2000 as *const ())
+        .with_condition(Condition::Execution)
+        .with_enabled(true);
+
+    assert_eq!(context.slots().free, 4);
+
+    context.set_breakpoint(first);
+    context.set_breakpoint(third);
+
+    let slots = context.slots();
+    assert_eq!(slots.free, 2);
+    assert_eq!(
+        slots.used,
+        [
+            Some(context.breakpoint(Index::First)),
+            None,
+            Some(context.breakpoint(Index::Third)),
+            None
+        ]
+    );
+    assert_eq!(slots.used[0].map(|bp| bp.address), Some(first.address));
+    assert_eq!(slots.used[2].map(|bp| bp.address), Some(third.address));
+}
+
+#[test]
+fn read_only_debug_registers() {
+    // Shaped like an exception handler that only gets to look at the context
+    fn inspect(
+        context: &HwbpContext<&CONTEXT>,
+    ) -> (Dr6<crate::PseudoUsize>, Dr7<crate::PseudoUsize>) {
+        (context.dr6(), context.dr7())
+    }
+
+    let mut record = unsafe { std::mem::zeroed::<CONTEXT>() };
+    HwbpContext::from_context(&mut record).set_breakpoint(
+        Hwbp::second()
+            .with_address(0x2000 as *const ())
+            .with_size(Size::Two)
+            .with_condition(Condition::ReadWrite)
+            .with_enabled(true),
+    );
+    record.Dr6 = 0b0010;
+
+    // The wrappers are copies, so they outlive the context they were read from
+    let (dr6, dr7) = inspect(&HwbpContext::from_context(&record));
+
+    assert!(dr6.breakpoint_at(Index::Second));
+    assert!(!dr6.breakpoint_at(Index::First));
+    assert!(dr7.enabled_local(Index::Second));
+    assert_eq!(dr7.size(Index::Second), Size::Two);
+    assert_eq!(dr7.condition(Index::Second), Condition::ReadWrite);
+}
+
+#[test]
+fn breakpoints_roundtrip() {
+    let mut source = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    source.set_breakpoint(
+        Hwbp::second()
+            .with_address(0x1000 as *const ())
+            .with_size(Size::Two)
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    source.set_breakpoint(
+        Hwbp::fourth()
+            .with_address(0x2000 as *const ())
+            .with_condition(Condition::Execution)
+            .with_enabled(true),
+    );
+
+    let all = source.all();
+    assert_eq!(all[Index::Second], source.breakpoint(Index::Second));
+    assert_eq!(all.into_iter().filter(|bp| bp.enabled).count(), 2);
+
+    // Writing them to a fresh context yields the same breakpoints
+    let mut target = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    target.set_all(all);
+    assert_eq!(target.all(), all);
+}
+
+#[test]
+fn enable_into_context() {
+    static WATCHED: u8 = 0;
+
+    unsafe {
+        // Nothing ever writes to `WATCHED`, so this can't be hit
+        let bp = Hwbp::fourth()
+            .with_address(&WATCHED)
+            .with_condition(Condition::Write);
+
+        let context = bp
+            .enable_into_context()
+            .expect("failed to enable write breakpoint");
+        assert_eq!(context.breakpoint(Index::Fourth), bp.with_enabled(true));
+
+        bp.disable().expect("failed to disable write breakpoint");
+    }
+}
+
+#[test]
+fn io_breakpoints_rejected() {
+    assert!(!Condition::io_breakpoints_supported());
+
+    let bp = Hwbp::first().with_condition(Condition::IoReadWrite);
+    assert_eq!(bp.validate(), Err(BreakpointError::IoUnsupported));
+}
+
+#[test]
+fn io_breakpoints_fail_to_enable() {
+    use crate::HwbpError;
+
+    static WATCHED: u8 = 0;
+
+    let io = Hwbp::first()
+        .with_address(&WATCHED)
+        .with_condition(Condition::IoReadWrite);
+
+    unsafe {
+        assert_eq!(io.enable(), Err(HwbpError::UnsupportedCondition));
+        assert_eq!(
+            io.enable_into_context().err(),
+            Some(HwbpError::UnsupportedCondition)
+        );
+    }
+
+    // Nothing was applied to the thread
+    let context = HwbpContext::get().expect("failed to get context");
+    assert!(!context.breakpoint(Index::First).enabled);
+
+    // Other conditions are unaffected
+    let write = io.with_condition(Condition::Write);
+    unsafe {
+        let enabled = write.enable().expect("failed to enable breakpoint");
+        assert!(enabled.enabled);
+        enabled.disable().expect("failed to disable breakpoint");
+    }
+}
+
+#[test]
+fn parse_condition_and_size() {
+    use crate::ParseError;
+
+    for (s, condition) in [
+        ("x", Condition::Execution),
+        ("exec", Condition::Execution),
+        ("Execution", Condition::Execution),
+        ("w", Condition::Write),
+        ("WRITE", Condition::Write),
+        ("rw", Condition::ReadWrite),
+        ("readwrite", Condition::ReadWrite),
+        ("io", Condition::IoReadWrite),
+    ] {
+        assert_eq!(s.parse(), Ok(condition), "{}", s);
+    }
+
+    for (s, size) in [
+        ("1", Size::One),
+        ("byte", Size::One),
+        ("2", Size::Two),
+        ("word", Size::Two),
+        ("4", Size::Four),
+        ("DWORD", Size::Four),
+        ("8", Size::Eight),
+        ("qword", Size::Eight),
+    ] {
+        assert_eq!(s.parse(), Ok(size), "{}", s);
+    }
+
+    assert_eq!(
+        "read".parse::<Condition>(),
+        Err(ParseError::InvalidCondition("read".to_owned()))
+    );
+    assert_eq!(
+        "3".parse::<Size>(),
+        Err(ParseError::InvalidSize("3".to_owned()))
+    );
+}
+
+#[test]
+fn parse_spec() {
+    use crate::ParseError;
+
+    let spec = |s| Hwbp::from_spec(s).map(|bp| (bp.condition, bp.size, bp.address as usize));
+
+    // Everything specified
+    assert_eq!(
+        spec("rw:4@0x1000"),
+        Ok((Condition::ReadWrite, Size::Four, 0x1000))
+    );
+    assert_eq!(
+        spec("w:qword@0X2000"),
+        Ok((Condition::Write, Size::Eight, 0x2000))
+    );
+
+    // Defaults
+    assert_eq!(
+        spec("exec@0x401000"),
+        Ok((Condition::Execution, Size::One, 0x401000))
+    );
+    assert_eq!(spec(":2@4096"), Ok((Condition::Execution, Size::Two, 4096)));
+    assert_eq!(
+        spec("0x401000"),
+        Ok((Condition::Execution, Size::One, 0x401000))
+    );
+
+    // Parsed breakpoints start out disabled at the first index
+    let bp = Hwbp::from_spec("w@0x1000").unwrap();
+    assert_eq!(
+        bp,
+        Hwbp::first()
+            .with_address(bp.address)
+            .with_condition(Condition::Write)
+    );
+
+    // Errors
+    assert_eq!(
+        spec("r@0x1000"),
+        Err(ParseError::InvalidCondition("r".to_owned()))
+    );
+    assert_eq!(
+        spec("rw:3@0x1000"),
+        Err(ParseError::InvalidSize("3".to_owned()))
+    );
+    assert_eq!(
+        spec("rw@0xzz"),
+        Err(ParseError::InvalidAddress("0xzz".to_owned()))
+    );
+    assert_eq!(spec(""), Err(ParseError::InvalidAddress("".to_owned())));
+}
+
+#[test]
+fn contains_and_find() {
+    let bp = Hwbp::third()
+        .with_address(0x1000 as *const ())
+        .with_size(Size::Two)
+        .with_condition(Condition::Write);
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint(bp);
+
+    // The index doesn't matter, the enabled flag only does when asked to
+    assert!(context.contains(&bp.at_index(Index::First), false));
+    assert!(context.contains(&bp, true));
+    assert!(!context.contains(&bp.with_enabled(true), true));
+    assert!(!context.contains(&bp.with_size(Size::Four), false));
+
+    // Only enabled breakpoints are found
+    assert_eq!(context.find(0x1000 as *const ()), None);
+
+    context.set_breakpoint(bp.with_enabled(true));
+    assert_eq!(
+        context.find(0x1000 as *const ()),
+        Some(bp.with_enabled(true))
+    );
+    assert_eq!(context.find(0x1001 as *const ()), None);
+}
+
+#[test]
+fn const_construction() {
+    const TABLE: [Hwbp; 2] = [
+        Hwbp::first()
+            .with_size(Size::Four)
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+        Hwbp::from_index(Index::Third).with_address(0x1000 as *const ()),
+    ];
+
+    assert_eq!(TABLE[0].size, Size::Four);
+    assert_eq!(TABLE[1].index, Index::Third);
+    assert_eq!(TABLE[1].address as usize, 0x1000);
+}
+
+#[test]
+fn acknowledge() {
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let mut context = HwbpContext::from_context(&mut raw);
+
+    context.set_breakpoint(
+        Hwbp::first()
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    context.set_breakpoint(
+        Hwbp::second()
+            .with_condition(Condition::Execution)
+            .with_enabled(true),
+    );
+
+    // A disabled slot can still be flagged, and an all-zero condition decodes as execution, but
+    // it's not a breakpoint that needs resuming past
+    *context.dr6_mut().0 |= 0b0100;
+    context.acknowledge();
+    assert!(!context.dr6().breakpoint());
+    assert!(!context.eflags().resume());
+
+    // A data breakpoint only resets Dr6
+    *context.dr6_mut().0 |= 0b0001;
+    context.acknowledge();
+    assert!(!context.dr6().breakpoint());
+    assert!(!context.eflags().resume());
+
+    // An execution breakpoint also sets the resume flag
+    *context.dr6_mut().0 |= 0b0010;
+    context.acknowledge();
+    assert!(!context.dr6().breakpoint());
+    assert!(context.eflags().resume());
+}
+
+#[test]
+fn index_out_of_range() {
+    use crate::IndexOutOfRange;
+    use std::convert::TryFrom;
+
+    assert_eq!(Index::try_from(3), Ok(Index::Fourth));
+    assert_eq!(Index::try_from(7), Err(IndexOutOfRange(7)));
+    assert_eq!(
+        IndexOutOfRange(7).to_string(),
+        "debug register index 7 out of range 0..=3"
+    );
+}
+
+#[test]
+fn dispatcher_disarms_data_breakpoints() {
+    use crate::dispatcher::Dispatcher;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static SEEN_ENABLED: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    fn count_enabled(context: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {
+        SEEN_ENABLED.store(context.enabled_breakpoints().count(), Ordering::Relaxed);
+    }
+
+    fn clear_own(context: &mut HwbpContext<&mut CONTEXT>, hwbp: Hwbp) {
+        context.clear_breakpoint(hwbp.index);
+    }
+
+    let dispatcher = Dispatcher::new();
+    dispatcher.register(Index::First, count_enabled);
+
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let mut context = HwbpContext::from_context(&mut raw);
+    context.set_breakpoint(
+        Hwbp::first()
+            .with_condition(Condition::ReadWrite)
+            .with_enabled(true),
+    );
+    context.set_breakpoint(
+        Hwbp::third()
+            .with_condition(Condition::Execution)
+            .with_enabled(true),
+    );
+    let before = context.read_table();
+
+    // Everything is disabled while the callback runs, and enabled again afterwards
+    *context.dr6_mut().0 = 0b0001;
+    assert!(dispatcher.dispatch(&mut context));
+    assert_eq!(SEEN_ENABLED.load(Ordering::Relaxed), 0);
+    assert_eq!(context.read_table(), before);
+
+    // Unless disarming is turned off
+    dispatcher.set_disarm_during_callback(false);
+    *context.dr6_mut().0 = 0b0001;
+    assert!(dispatcher.dispatch(&mut context));
+    assert_eq!(SEEN_ENABLED.load(Ordering::Relaxed), 2);
+    dispatcher.set_disarm_during_callback(true);
+
+    // Changes made by the callback are kept
+    dispatcher.register(Index::First, clear_own);
+    *context.dr6_mut().0 = 0b0001;
+    assert!(dispatcher.dispatch(&mut context));
+    assert!(!context.breakpoint(Index::First).enabled);
+    assert!(context.breakpoint(Index::Third).enabled);
+}
+
+#[test]
+fn dispatcher_step_count() {
+    use crate::dispatcher::{self, Dispatcher};
+    use crate::registers::EFlags;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static LAST_LEFT: AtomicU32 = AtomicU32::new(u32::MAX);
+
+    fn step_twice(context: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {
+        context.step_count(2);
+    }
+
+    fn on_step(_: &mut HwbpContext<&mut CONTEXT>, left: u32) {
+        LAST_LEFT.store(left, Ordering::Relaxed);
+    }
+
+    let dispatcher = Dispatcher::new();
+    dispatcher.register(Index::First, step_twice);
+    dispatcher.register_step(on_step);
+
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let single_step = 1 << 14;
+
+    // The breakpoint hit starts the stepping
+    raw.Dr6 = 0b0001;
+    assert!(dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw)));
+    assert!(EFlags(raw.EFlags).trap());
+    assert_eq!(dispatcher::steps_left(), 2);
+
+    // Every single-step exception counts down, until the trap flag is cleared
+    raw.Dr6 = single_step;
+    assert!(dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw)));
+    assert_eq!(LAST_LEFT.load(Ordering::Relaxed), 1);
+    assert!(EFlags(raw.EFlags).trap());
+
+    raw.Dr6 = single_step;
+    assert!(dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw)));
+    assert_eq!(LAST_LEFT.load(Ordering::Relaxed), 0);
+    assert!(!EFlags(raw.EFlags).trap());
+
+    // Further single-step exceptions aren't ours
+    raw.Dr6 = single_step;
+    assert!(!dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw)));
+    assert_eq!(dispatcher::steps_left(), 0);
+}
+
+#[test]
+fn confirm_execution_breakpoint() {
+    fn set_ip(record: &mut CONTEXT, ip: usize) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            record.Rip = ip as u64;
+        }
+        #[cfg(target_arch = "x86")]
+        {
+            record.Eip = ip as u32;
+        }
+    }
+
+    let mut record = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let mut context = HwbpContext::from_context(&mut record);
+    context.set_breakpoint(
+        Hwbp::second()
+            .with_address(0x1000 as *const ())
+            .with_condition(Condition::Execution)
+            .with_enabled(true),
+    );
+    context.set_breakpoint(
+        Hwbp::third()
+            .with_address(0x2000 as *const ())
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    record.Dr6 = 0b0110;
+
+    set_ip(&mut record, 0x1000);
+    let context = HwbpContext::from_context(&record);
+    assert!(context.confirm_execution_breakpoint(Index::Second));
+    assert!(!context.confirm_execution_breakpoint(Index::First));
+
+    // Data breakpoints are never confirmed, even if the address matches
+    set_ip(&mut record, 0x2000);
+    let context = HwbpContext::from_context(&record);
+    assert!(!context.confirm_execution_breakpoint(Index::Third));
+
+    // A stale flag with the instruction pointer elsewhere isn't attributed to it
+    assert!(!context.confirm_execution_breakpoint(Index::Second));
+
+    // Neither is a matching instruction pointer without the flag
+    set_ip(&mut record, 0x1000);
+    record.Dr6 = 0;
+    let context = HwbpContext::from_context(&record);
+    assert!(!context.confirm_execution_breakpoint(Index::Second));
+}
+
+#[test]
+fn dispatcher_hit_log() {
+    use crate::dispatcher::{Dispatcher, HIT_LOG_CAPACITY};
+
+    fn ignore(_: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {}
+
+    let dispatcher = Dispatcher::new();
+    dispatcher.register(Index::First, ignore);
+    dispatcher.register(Index::Third, ignore);
+
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let mut hit = |dr6: u64, ip: usize| {
+        raw.Dr6 = dr6 as _;
+        #[cfg(target_arch = "x86_64")]
+        {
+            raw.Rip = ip as u64;
+        }
+        #[cfg(target_arch = "x86")]
+        {
+            raw.Eip = ip as u32;
+        }
+        dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw));
+    };
+
+    // Nothing is recorded until the log is enabled
+    hit(0b0001, 0x1000);
+    assert!(dispatcher.drain_log().is_empty());
+
+    dispatcher.set_hit_log(true);
+    hit(0b0001, 0x1001);
+    hit(0b0100, 0x1002);
+    hit(0b0101, 0x1003);
+
+    let log = dispatcher.drain_log();
+    let entries: Vec<_> = log
+        .iter()
+        .map(|hit| (hit.index, hit.instruction_pointer))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            (Index::First, 0x1001),
+            (Index::Third, 0x1002),
+            (Index::First, 0x1003),
+            (Index::Third, 0x1003),
+        ]
+    );
+    assert!(log.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+    // Draining empties the log
+    assert!(dispatcher.drain_log().is_empty());
+
+    // Only the newest hits are kept once the log overflows
+    for i in 0..HIT_LOG_CAPACITY + 10 {
+        hit(0b0001, i);
+    }
+    let log = dispatcher.drain_log();
+    assert_eq!(log.len(), HIT_LOG_CAPACITY);
+    assert_eq!(log[0].instruction_pointer, 10);
+    assert_eq!(
+        log[HIT_LOG_CAPACITY - 1].instruction_pointer,
+        HIT_LOG_CAPACITY + 9
+    );
+}
+
+#[test]
+fn dispatcher_hit_counts() {
+    use crate::dispatcher::Dispatcher;
+
+    fn ignore(_: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {}
+
+    let dispatcher = Dispatcher::new();
+    dispatcher.register(Index::Second, ignore);
+    dispatcher.register(Index::Fourth, ignore);
+
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+
+    // Hit the second breakpoint three times, and the fourth once alongside it
+    for dr6 in [0b0010, 0b0010, 0b1010] {
+        raw.Dr6 = dr6;
+        dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw));
+    }
+
+    // Hits on indices without a callback aren't counted
+    raw.Dr6 = 0b0001;
+    dispatcher.dispatch(&mut HwbpContext::from_context(&mut raw));
+
+    assert_eq!(dispatcher.counts(), [0, 3, 0, 1]);
+
+    dispatcher.reset_counts();
+    assert_eq!(dispatcher.counts(), [0; 4]);
+}
+
+#[test]
+fn dispatcher_resumes_past_execution_breakpoints() {
+    use crate::dispatcher::Dispatcher;
+
+    fn ignore(_: &mut HwbpContext<&mut CONTEXT>, _: Hwbp) {}
+
+    let dispatcher = Dispatcher::new();
+    dispatcher.register(Index::Second, ignore);
+
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let mut context = HwbpContext::from_context(&mut raw);
+    context.set_breakpoint(
+        Hwbp::second()
+            .with_condition(Condition::Execution)
+            .with_enabled(true),
+    );
+
+    // Without the resume flag, the instruction would trigger the breakpoint again right away
+    *context.dr6_mut().0 = 0b0010;
+    assert!(dispatcher.dispatch(&mut context));
+    assert!(!context.dr6().breakpoint());
+    assert!(context.eflags().resume());
+}
+
+#[test]
+fn breakpoints_by_dr6_indexed() {
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    raw.Dr6 = 0b1001;
+
+    let mut context = HwbpContext::from_context(&mut raw);
+    context.set_breakpoint(Hwbp::fourth().with_address(0x1000 as *const ()));
+
+    // Both set flags are reported, with the matching breakpoint
+    let hits = context.breakpoints_by_dr6_indexed().collect::<Vec<_>>();
+    assert_eq!(
+        hits,
+        [
+            (Index::First, context.breakpoint(Index::First)),
+            (Index::Fourth, context.breakpoint(Index::Fourth)),
+        ]
+    );
+    assert_eq!(hits[1].1.address as usize, 0x1000);
+}
+
+#[test]
+fn without_breakpoints() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint(Hwbp::first().with_enabled(true));
+    context.set_breakpoint(Hwbp::third().with_enabled(true));
+    context.dr7_mut().set_enabled_global(Index::Third, true);
+
+    let before = context.all();
+
+    // Everything is disabled while the closure runs
+    let result = context.without_breakpoints(|context| {
+        assert_eq!(context.enabled_breakpoints().count(), 0);
+        assert!(!context.dr7().enabled_global(Index::Third));
+        42
+    });
+
+    // And restored afterwards
+    assert_eq!(result, 42);
+    assert_eq!(context.all(), before);
+    assert!(context.dr7().enabled_global(Index::Third));
+}
+
+#[test]
+fn disable_all_and_enable_previously_set() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    let local = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_enabled(true);
+    let disabled = Hwbp::second()
+        .with_address(0x2000 as *const ())
+        .with_enabled(false);
+    let global = Hwbp::third()
+        .with_address(0x3000 as *const ())
+        .with_global(true);
+    context.set_breakpoint(local);
+    context.set_breakpoint(disabled);
+    context.set_breakpoint(global);
+    let before = context.read_table();
+
+    let cleared = context.disable_all();
+    assert_eq!(cleared.local, [true, false, false, false]);
+    assert_eq!(cleared.global, [false, false, true, false]);
+    assert_eq!(context.slots().free, 4);
+
+    // Only the cleared bits come back, so the disabled breakpoint stays disabled and the global
+    // one doesn't turn into a local one
+    context.enable_all_previously_set(cleared);
+    assert_eq!(context.read_table(), before);
+    assert!(!context.breakpoint(Index::Second).is_in_use());
+    assert!(!context.breakpoint(Index::Third).enabled);
+    assert!(context.breakpoint(Index::Third).global);
+}
+
+#[test]
+fn total_watched_bytes() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    assert_eq!(context.total_watched_bytes(), 0);
+
+    let data = |bp: Hwbp, size, condition| bp.with_size(size).with_condition(condition);
+
+    context
+        .set_breakpoint(data(Hwbp::first(), Size::Eight, Condition::ReadWrite).with_enabled(true));
+    context.set_breakpoint(data(Hwbp::second(), Size::Two, Condition::Write).with_enabled(true));
+    context.set_breakpoint(data(Hwbp::third(), Size::One, Condition::Execution).with_enabled(true));
+
+    // Disabled breakpoints aren't counted
+    context.set_breakpoint(data(Hwbp::fourth(), Size::Four, Condition::Write));
+
+    assert_eq!(context.total_watched_bytes(), 8 + 2);
+}
+
+#[test]
+fn apply_to_thread() {
+    use crate::context::FetchWith;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::mpsc;
+
+    static WATCHED: u8 = 0;
+
+    // Park a thread until we're done with it
+    let (done, wait) = mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || wait.recv().ok());
+    let handle = thread.as_raw_handle() as _;
+
+    let bp = Hwbp::second()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    unsafe { bp.apply_to_thread(handle) }.expect("failed to apply breakpoint to thread");
+
+    // The breakpoint is now set on the other thread
+    let context = HwbpContext::get_with(FetchWith::GetThreadContextOther(handle))
+        .expect("failed to get context");
+    assert_eq!(context.breakpoint(Index::Second), bp);
+
+    done.send(()).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn breakpoints_of_process() {
+    use crate::process;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::mpsc;
+    use winapi::um::processthreadsapi::{GetCurrentProcessId, GetCurrentThreadId, GetThreadId};
+
+    static WATCHED: u8 = 0;
+
+    // Park a thread until we're done with it
+    let (done, wait) = mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || wait.recv().ok());
+    let handle = thread.as_raw_handle() as _;
+
+    let bp = Hwbp::third()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    unsafe { bp.apply_to_thread(handle) }.expect("failed to apply breakpoint to thread");
+
+    let threads = process::breakpoints_of_process(unsafe { GetCurrentProcessId() })
+        .expect("failed to get breakpoints of process");
+
+    // The parked thread reports the breakpoint, and the current thread is included too
+    let tid = unsafe { GetThreadId(handle) };
+    let (_, breakpoints) = threads
+        .iter()
+        .find(|(id, _)| *id == tid)
+        .expect("parked thread missing");
+    assert_eq!(breakpoints[Index::Third as usize], bp);
+    assert!(threads
+        .iter()
+        .any(|(id, _)| *id == unsafe { GetCurrentThreadId() }));
+
+    done.send(()).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+#[ignore = "places a breakpoint on every thread, run with `--ignored --test-threads=1`"]
+fn install_persistent() {
+    use crate::process;
+
+    static WATCHED: u8 = 0;
+
+    let bp = Hwbp::third()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write);
+
+    let installed = unsafe { process::install_persistent(bp) }.expect("failed to install");
+    assert!(installed >= 1);
+    assert_eq!(
+        HwbpContext::get()
+            .expect("failed to get context")
+            .breakpoint(Index::Third)
+            .address,
+        bp.address
+    );
+
+    // A thread created after installing gets the breakpoint before it runs
+    let seen = std::thread::spawn(|| {
+        let bp = HwbpContext::get()
+            .expect("failed to get context")
+            .breakpoint(Index::Third);
+        (bp.enabled, bp.address as usize)
+    })
+    .join()
+    .unwrap();
+    assert_eq!(seen, (true, bp.address as usize));
+
+    // Once uninstalled, new threads don't get it anymore
+    assert_eq!(
+        process::uninstall_persistent(Index::Third).map(|bp| bp.address),
+        Some(bp.address)
+    );
+    process::clear_breakpoints_all_threads().expect("failed to clear breakpoints");
+
+    let enabled = std::thread::spawn(|| {
+        HwbpContext::get()
+            .expect("failed to get context")
+            .enabled_breakpoints()
+            .count()
+    })
+    .join()
+    .unwrap();
+    assert_eq!(enabled, 0);
+}
+
+#[test]
+#[ignore = "clears the breakpoints of every thread, run with `--ignored --test-threads=1`"]
+fn clear_breakpoints_all_threads() {
+    use crate::context::FetchWith;
+    use crate::process;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::mpsc;
+
+    static WATCHED: u8 = 0;
+
+    // Park a thread until we're done with it
+    let (done, wait) = mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || wait.recv().ok());
+    let handle = thread.as_raw_handle() as _;
+
+    let bp = Hwbp::fourth()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    unsafe {
+        bp.apply_to_thread(handle)
+            .expect("failed to apply breakpoint to thread");
+        bp.apply().expect("failed to apply breakpoint");
+    }
+
+    // At least the parked thread and the current thread were cleared
+    let cleared = process::clear_breakpoints_all_threads().expect("failed to clear breakpoints");
+    assert!(cleared >= 2);
+
+    let other = HwbpContext::get_with(FetchWith::GetThreadContextOther(handle))
+        .expect("failed to get context");
+    let current = HwbpContext::get().expect("failed to get context");
+    assert_eq!(other.enabled_breakpoints().count(), 0);
+    assert_eq!(current.enabled_breakpoints().count(), 0);
+    assert_eq!(other.breakpoint(Index::Fourth).address, std::ptr::null());
+
+    done.send(()).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn resolve_module_offset() {
+    use crate::{process, HwbpError};
+    use winapi::um::libloaderapi::GetModuleHandleW;
+
+    let exe = std::env::current_exe().expect("failed to get executable path");
+    let name = exe.file_name().unwrap().to_str().unwrap();
+    let base = unsafe { GetModuleHandleW(null_mut()) } as usize;
+
+    assert_eq!(process::resolve(name, 0), Some(base));
+    assert_eq!(process::resolve(name, 0x1234), Some(base + 0x1234));
+    assert_eq!(
+        process::resolve(&name.to_uppercase(), 0x1234),
+        Some(base + 0x1234)
+    );
+
+    let bp = Hwbp::at_module_offset(name, 0x1234, Condition::Execution)
+        .expect("failed to resolve module");
+    assert_eq!(bp.address_usize(), base + 0x1234);
+    assert_eq!(bp.condition, Condition::Execution);
+    assert!(!bp.enabled);
+
+    let missing = "hwbp-no-such-module.dll";
+    assert_eq!(process::resolve(missing, 0), None);
+    assert_eq!(
+        Hwbp::at_module_offset(missing, 0, Condition::Execution),
+        Err(HwbpError::ModuleNotFound)
+    );
+}
+
+#[test]
+fn context_source() {
+    use crate::context::ContextSource;
+    use crate::HwbpError;
+    use std::cell::{Cell, RefCell};
+
+    // A thread that only exists as a stored context, counting how often it's used
+    struct Stored {
+        context: RefCell<CONTEXT>,
+        fetched: Cell<u32>,
+        applied: Cell<u32>,
+    }
+
+    impl ContextSource for Stored {
+        fn fetch(&self, context: &mut CONTEXT) -> Result<(), HwbpError> {
+            self.fetched.set(self.fetched.get() + 1);
+            *context = *self.context.borrow();
+            Ok(())
+        }
+
+        fn apply(&self, context: &CONTEXT) -> Result<(), HwbpError> {
+            self.applied.set(self.applied.get() + 1);
+            *self.context.borrow_mut() = *context;
+            Ok(())
+        }
+    }
+
+    let stored = Stored {
+        context: RefCell::new(unsafe { std::mem::zeroed() }),
+        fetched: Cell::new(0),
+        applied: Cell::new(0),
+    };
+    let source: &dyn ContextSource = &stored;
+
+    let bp = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    let mut context = HwbpContext::get_from(source).expect("failed to get context");
+    assert!(!context.breakpoint(Index::Second).enabled);
+    context.set_breakpoint(bp);
+    unsafe { context.apply_to(source) }.expect("failed to apply context");
+
+    let context = HwbpContext::get_from(source).expect("failed to get context");
+    assert_eq!(context.breakpoint(Index::Second), bp);
+    assert_eq!((stored.fetched.get(), stored.applied.get()), (2, 1));
+}
+
+#[test]
+fn thread_handle_source() {
+    use crate::context::{ContextSource, ThreadHandleSource};
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::mpsc;
+
+    static WATCHED: u8 = 0;
+
+    // Park a thread until we're done with it
+    let (done, wait) = mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || wait.recv().ok());
+    let source: &dyn ContextSource = &ThreadHandleSource(thread.as_raw_handle() as _);
+
+    let bp = Hwbp::first()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    let mut context = HwbpContext::get_from(source).expect("failed to get context");
+    context.set_breakpoint(bp);
+    unsafe { context.apply_to(source) }.expect("failed to apply context");
+
+    let other = HwbpContext::get_from(source).expect("failed to get context");
+    let current = HwbpContext::get().expect("failed to get context");
+    assert_eq!(other.breakpoint(Index::First), bp);
+    assert!(!current.breakpoint(Index::First).enabled);
+
+    done.send(()).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn apply_returning_previous() {
+    static WATCHED: u8 = 0;
+
+    let bp = Hwbp::third()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    let mut context = HwbpContext::get().expect("failed to get context");
+    assert!(!context.breakpoint(Index::Third).enabled);
+    context.set_breakpoint(bp);
+
+    // The returned context is from before the breakpoint was applied
+    let previous = unsafe { context.apply_returning_previous() }.expect("failed to apply context");
+    assert!(!previous.breakpoint(Index::Third).enabled);
+
+    let current = HwbpContext::get().expect("failed to get context");
+    assert_eq!(current.breakpoint(Index::Third), bp);
+
+    // It only holds the debug registers, so applying it only rolls the breakpoint back
+    unsafe { previous.apply() }.expect("failed to restore context");
+    let restored = HwbpContext::get().expect("failed to get context");
+    assert!(!restored.breakpoint(Index::Third).enabled);
+    assert_eq!(restored.read_table(), previous.read_table());
+}
+
+#[test]
+fn apply_to_current() {
+    use crate::context::FetchWith;
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::mpsc;
+
+    static WATCHED: u8 = 0;
+
+    // Park a thread until we're done with it
+    let (done, wait) = mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || wait.recv().ok());
+    let handle = thread.as_raw_handle() as _;
+
+    let bp = Hwbp::second()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    // Fetch the other thread's context, but apply it to this one
+    let mut context = HwbpContext::get_with(FetchWith::GetThreadContextOther(handle))
+        .expect("failed to get context");
+    context.set_breakpoint(bp);
+    unsafe { context.apply_to_current() }.expect("failed to apply context");
+
+    let current = HwbpContext::get().expect("failed to get context");
+    let other = HwbpContext::get_with(FetchWith::GetThreadContextOther(handle))
+        .expect("failed to get context");
+    assert_eq!(current.breakpoint(Index::Second), bp);
+    assert!(!other.breakpoint(Index::Second).enabled);
+
+    unsafe { bp.disable() }.expect("failed to disable breakpoint");
+
+    done.send(()).unwrap();
+    thread.join().unwrap();
+}
+
+#[test]
+fn send_hwbp() {
+    use crate::SendHwbp;
+    use std::sync::mpsc;
+
+    static WATCHED: u8 = 0;
+
+    let bp = Hwbp::third()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+
+    let (tx, rx) = mpsc::channel::<SendHwbp>();
+    let worker = std::thread::spawn(move || {
+        let bp = Hwbp::from(rx.recv().unwrap());
+        unsafe { bp.apply() }.expect("failed to apply breakpoint");
+
+        let context = HwbpContext::get().expect("failed to get context");
+        SendHwbp(context.breakpoint(Index::Third))
+    });
+
+    tx.send(bp.into()).unwrap();
+    assert_eq!(Hwbp::from(worker.join().unwrap()), bp);
+}
+
+#[test]
+fn high_address() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    // Top of the address space, aligned for every size
+    let address = usize::MAX & !7;
+    context.set_breakpoint(
+        Hwbp::first()
+            .with_address(address as *const ())
+            .with_size(Size::Eight),
+    );
+
+    let bp = context.breakpoint(Index::First);
+    assert_eq!(bp.address_usize(), address);
+    assert_eq!(context.into_context().Dr0 as usize, address);
+}
+
+#[test]
+fn local_and_global_enable() {
+    let mut record = unsafe { std::mem::zeroed::<CONTEXT>() };
+    record.Dr7 = DR7_RESERVED;
+    let mut context = HwbpContext::from_context(&mut record);
+
+    let bp = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write);
+    context.set_breakpoint(bp.with_enabled(true));
+    context.set_breakpoint_global(bp.at_index(Index::Third).with_enabled(true));
+    context.set_breakpoint(
+        bp.at_index(Index::Fourth)
+            .with_enabled(true)
+            .with_global(true),
+    );
+
+    let local = raw::get_breakpoint(&record, Index::Second);
+    assert!(local.is_local_enabled() && !local.is_global_enabled());
+
+    let global = raw::get_breakpoint(&record, Index::Third);
+    assert!(!global.is_local_enabled() && global.is_global_enabled());
+
+    let both = raw::get_breakpoint(&record, Index::Fourth);
+    assert!(both.is_local_enabled() && both.is_global_enabled());
+
+    // Globally enabled slots are in use, even without the local bit
+    assert_eq!(raw::used_mask(&record), [false, true, true, true]);
+    assert_eq!(raw::first_unused(&record), Some(Index::First));
+
+    // Both bits roundtrip through compose_dr7
+    let table = HwbpContext::from_context(&record).read_table();
+    assert_eq!(raw::compose_dr7(table), record.Dr7);
+}
+
+#[test]
+fn with_global_enable() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    let bp = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    context.set_breakpoint(bp);
+
+    let seen = context.with_global_enable(Index::Second, |context| {
+        let bp = context.breakpoint(Index::Second);
+        (bp.is_local_enabled(), bp.is_global_enabled())
+    });
+    assert_eq!(seen, (true, true));
+
+    // Only the global bit is restored, the local one is untouched
+    assert_eq!(context.breakpoint(Index::Second), bp);
+
+    // A slot that was already global stays global
+    context.set_breakpoint(bp.with_global(true));
+    context.with_global_enable(Index::Second, |_| ());
+    assert!(context.breakpoint(Index::Second).is_global_enabled());
+
+    // Other slots aren't affected
+    context.with_global_enable(Index::Fourth, |context| {
+        assert!(context.dr7().enabled_global(Index::Fourth));
+        assert!(!context.dr7().enabled_global(Index::First));
+    });
+    assert!(!context.dr7().enabled_global(Index::Fourth));
+}
+
+#[test]
+fn set_breakpoint_global() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let bp = Hwbp::third()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    context.set_breakpoint_global(bp);
+
+    assert!(context.dr7().enabled_global(Index::Third));
+    assert!(!context.dr7().enabled_local(Index::Third));
+    assert_eq!(
+        context.breakpoint(Index::Third),
+        bp.with_enabled(false).with_global(true)
+    );
+
+    // Disabling it clears the global bit again
+    context.set_breakpoint_global(bp.with_enabled(false));
+    assert!(!context.dr7().enabled_global(Index::Third));
+}
+
+#[test]
+fn dr6_selective_clear() {
+    let all = 0b1111u64 | 1 << 14 | 1 << 16;
+
+    let mut dr6 = Dr6(all);
+    dr6.clear_breakpoint(Index::Second);
+    assert_eq!(dr6.0, all & !0b0010);
+    assert!(dr6.breakpoint_at(Index::First));
+    assert!(!dr6.breakpoint_at(Index::Second));
+
+    let mut dr6 = Dr6(all);
+    dr6.clear_breakpoints([true, false, false, true]);
+    assert_eq!(dr6.0, all & !0b1001);
+
+    // Clearing an already clear flag does nothing
+    dr6.clear_breakpoint(Index::First);
+    assert_eq!(dr6.0, all & !0b1001);
+}
+
+#[test]
+fn used_mask_and_first_unused() {
+    let mut context = unsafe { std::mem::zeroed::<CONTEXT>() };
+    assert_eq!(raw::used_mask(&context), [false; 4]);
+    assert_eq!(raw::first_unused(&context), Some(Index::First));
+
+    let mut wrapped = HwbpContext::from_context(&mut context);
+    wrapped.set_breakpoint(Hwbp::first().with_enabled(true));
+    wrapped.set_breakpoint(Hwbp::third().with_enabled(true));
+    assert_eq!(raw::used_mask(&context), [true, false, true, false]);
+    assert_eq!(raw::first_unused(&context), Some(Index::Second));
+
+    // Agrees with `unused_breakpoint`
+    assert_eq!(
+        raw::first_unused(&context),
+        HwbpContext::from_context(&context)
+            .unused_breakpoint()
+            .map(|bp| bp.index)
+    );
+
+    let mut wrapped = HwbpContext::from_context(&mut context);
+    wrapped.set_breakpoint(Hwbp::second().with_enabled(true));
+    wrapped.set_breakpoint(Hwbp::fourth().with_enabled(true));
+    assert_eq!(raw::used_mask(&context), [true; 4]);
+    assert_eq!(raw::first_unused(&context), None);
+}
+
+#[test]
+fn refetch() {
+    let mut context = HwbpContext::get().expect("failed to get context");
+    let fetched = context.breakpoint(Index::Fourth);
+    let before: *const HwbpContext<CONTEXT> = &context;
+
+    // Modify the stored context without applying it
+    context.set_breakpoint(
+        Hwbp::fourth()
+            .with_address(0x1000 as *const ())
+            .with_enabled(true),
+    );
+
+    // Refetching overwrites the modification in place
+    context.refetch().expect("failed to refetch context");
+    assert_eq!(context.breakpoint(Index::Fourth), fetched);
+    assert_eq!(&context as *const _, before);
+}
+
+#[test]
+fn find_breakpoint_and_position() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let write = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    let exec = Hwbp::third()
+        .with_address(0x2000 as *const ())
+        .with_condition(Condition::Execution)
+        .with_enabled(true);
+    context.set_breakpoint(write);
+    context.set_breakpoint(exec);
+
+    // Matching
+    let is_exec = |bp: &Hwbp| bp.enabled && bp.condition.is_execution();
+    assert_eq!(context.find_breakpoint(is_exec), Some(exec));
+    assert_eq!(context.position(is_exec), Some(Index::Third));
+
+    let is_write_at = |bp: &Hwbp| bp.address_usize() == 0x1000 && bp.condition == Condition::Write;
+    assert_eq!(context.find_breakpoint(is_write_at), Some(write));
+    assert_eq!(context.position(is_write_at), Some(Index::Second));
+
+    // Non-matching
+    let is_io = |bp: &Hwbp| bp.condition.is_io();
+    assert_eq!(context.find_breakpoint(is_io), None);
+    assert_eq!(context.position(is_io), None);
+}
+
+#[test]
+fn from_raw() {
+    assert!(unsafe { HwbpContext::from_raw(null_mut()) }.is_none());
+
+    let mut raw = unsafe { std::mem::zeroed::<CONTEXT>() };
+    let bp = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_enabled(true);
+
+    let mut context = unsafe { HwbpContext::from_raw(&mut raw) }.expect("pointer is not null");
+    context.set_breakpoint(bp);
+
+    // Writes go through to the pointed-to context
+    assert_eq!(raw.Dr1, 0x1000);
+    assert_eq!(
+        HwbpContext::from_context(&raw).breakpoint(Index::Second),
+        bp
+    );
+}
+
+#[cfg(any(target_arch = "x86_64", feature = "ntapi"))]
+#[test]
+fn apply_with_availability() {
+    use crate::context::ApplyWith;
+
+    // `RtlRestoreContext` is only exported by ntdll on 64-bit
+    #[cfg(target_arch = "x86_64")]
+    assert_ne!(ApplyWith::RtlRestoreContext, ApplyWith::SetThreadContext);
+
+    // `NtContinue` is available on both, with the `ntapi` feature
+    #[cfg(feature = "ntapi")]
+    assert_ne!(ApplyWith::NtContinue, ApplyWith::SetThreadContext);
+}
+
+#[test]
+fn config_eq() {
+    let bp = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_size(Size::Four)
+        .with_condition(Condition::Write);
+
+    // The enabled flag is ignored
+    assert!(bp.config_eq(&bp));
+    assert!(bp.config_eq(&bp.with_enabled(true)));
+    assert_ne!(bp, bp.with_enabled(true));
+
+    // Everything else is compared
+    assert!(!bp.config_eq(&bp.with_index(Index::Third)));
+    assert!(!bp.config_eq(&bp.with_address(0x2000 as *const ())));
+    assert!(!bp.config_eq(&bp.with_size(Size::Two)));
+    assert!(!bp.config_eq(&bp.with_condition(Condition::ReadWrite)));
+}
+
+#[test]
+fn drain_triggered() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let first = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_enabled(true);
+    let third = Hwbp::third()
+        .with_address(0x2000 as *const ())
+        .with_enabled(true);
+    context.set_breakpoint(first);
+    context.set_breakpoint(third);
+
+    // Both breakpoints hit at once
+    *context.dr6_mut().0 = 0b0101 | 1 << 14;
+
+    let drained: Vec<_> = context.drain_triggered().collect();
+    assert_eq!(drained, [first, third]);
+
+    // Only the breakpoint flags are cleared
+    assert_eq!(context.dr6().0, 1 << 14);
+    assert_eq!(context.drain_triggered().count(), 0);
+}
+
+#[cfg(feature = "iced")]
+#[test]
+fn decode_instruction() {
+    use crate::context::FetchWith;
+
+    // Without an instruction pointer there's nothing to decode
+    let context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    assert_eq!(context.instruction_pointer(), 0);
+    assert!(unsafe { context.decode_instruction() }.is_none());
+
+    // `RtlCaptureContext` captures everything, pointing into our own code
+    let context =
+        HwbpContext::get_with(FetchWith::RtlCaptureContext).expect("failed to capture context");
+    let instruction = unsafe { context.decode_instruction() }.expect("failed to decode");
+    assert_eq!(instruction.ip() as usize, context.instruction_pointer());
+}
+
+#[test]
+fn clear_by_address() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let read = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_size(Size::Four)
+        .with_enabled(true);
+    let write = Hwbp::third()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    let elsewhere = Hwbp::fourth()
+        .with_address(0x2000 as *const ())
+        .with_enabled(true);
+    context.set_breakpoint(read);
+    context.set_breakpoint(write);
+    context.set_breakpoint(elsewhere);
+
+    // Both breakpoints at the address are cleared, the other one is left alone
+    assert_eq!(context.clear_by_address(0x1000 as *const ()), 2);
+    assert!(!context.breakpoint(Index::First).enabled);
+    assert!(!context.breakpoint(Index::Third).enabled);
+    assert_eq!(context.breakpoint(Index::Fourth), elsewhere);
+
+    // Nothing left to clear
+    assert_eq!(context.clear_by_address(0x1000 as *const ()), 0);
+}
+
+#[test]
+fn global_only_breakpoints_are_in_use() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint_global(
+        Hwbp::second()
+            .with_address(0x1000 as *const ())
+            .with_enabled(true),
+    );
+
+    // Every helper agrees that the slot is taken
+    assert!(context.breakpoint(Index::Second).is_in_use());
+    assert_eq!(context.enabled_breakpoints().count(), 1);
+    assert_eq!(context.used_indices().collect::<Vec<_>>(), [Index::Second]);
+    assert_eq!(context.slots().free, 3);
+    assert_ne!(
+        context.unused_breakpoint().map(|bp| bp.index),
+        Some(Index::Second)
+    );
+
+    assert_eq!(context.clear_by_address(0x1000 as *const ()), 1);
+    assert!(!context.breakpoint(Index::Second).is_in_use());
+}
+
+#[test]
+fn size_ordering() {
+    let sizes = [Size::One, Size::Two, Size::Four, Size::Eight];
+
+    // The derived ordering agrees with the byte length, despite `as_bits` ordering them differently
+    for a in sizes {
+        for b in sizes {
+            assert_eq!(a.cmp(&b), a.cmp_by_bytes(b));
+        }
+    }
+
+    let mut sorted = [Size::Eight, Size::One, Size::Four, Size::Two];
+    sorted.sort();
+    assert_eq!(sorted, sizes);
+
+    #[cfg(target_pointer_width = "64")]
+    assert_eq!(Size::max_for_arch(), Size::Eight);
+    #[cfg(target_pointer_width = "32")]
+    assert_eq!(Size::max_for_arch(), Size::Four);
+}
+
+#[test]
+fn index_bits() {
+    let indices = [Index::First, Index::Second, Index::Third, Index::Fourth];
+
+    assert_eq!(
+        indices.map(Index::dr6_mask),
+        [0b0001, 0b0010, 0b0100, 0b1000]
+    );
+    assert_eq!(indices.map(Index::dr7_local_bit), [0, 2, 4, 6]);
+    assert_eq!(indices.map(Index::dr7_global_bit), [1, 3, 5, 7]);
+
+    // And they agree with the register wrappers
+    for index in indices {
+        assert_eq!(Index::by_dr6_value(index.dr6_mask()), Some(index));
+
+        let mut dr7 = Dr7(0u64);
+        dr7.set_enabled_local(index, true);
+        assert_eq!(dr7.0, 1 << index.dr7_local_bit());
+
+        let mut dr7 = Dr7(0u64);
+        dr7.set_enabled_global(index, true);
+        assert_eq!(dr7.0, 1 << index.dr7_global_bit());
+    }
+}
+
+#[test]
+fn variant_names() {
+    let conditions = Condition::all().map(Condition::name);
+    assert_eq!(
+        conditions,
+        ["Execution", "Write", "ReadWrite", "IoReadWrite"]
+    );
+
+    let sizes = Size::all().map(Size::name);
+    assert_eq!(sizes, ["One", "Two", "Four", "Eight"]);
+
+    let indices = [Index::First, Index::Second, Index::Third, Index::Fourth].map(Index::name);
+    assert_eq!(indices, ["First", "Second", "Third", "Fourth"]);
+
+    // The names match the derived `Debug` output
+    for condition in Condition::all() {
+        assert_eq!(condition.name(), format!("{:?}", condition));
+    }
+}
+
+#[test]
+fn size_from_bytes() {
+    for size in Size::all() {
+        assert_eq!(Size::from_bytes(size.in_bytes()), Some(size));
+    }
+
+    for bytes in [0, 3, 5, 6, 7, 16, usize::MAX] {
+        assert_eq!(Size::from_bytes(bytes), None);
+    }
+
+    assert_eq!(
+        Size::from_bytes(std::mem::size_of::<u32>()),
+        Some(Size::Four)
+    );
+}
+
+#[test]
+fn max_breakpoints() {
+    assert_eq!(crate::MAX_BREAKPOINTS, Index::COUNT);
+    assert_eq!(crate::available_breakpoints(), Index::COUNT);
+
+    let context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    assert_eq!(context.breakpoints().count(), crate::MAX_BREAKPOINTS);
+}
+
+#[test]
+fn with_watch() {
+    let bp = Hwbp::second()
+        .with_watch(Condition::ReadWrite, Size::Four)
+        .expect("valid combination was rejected");
+    assert_eq!(bp.condition, Condition::ReadWrite);
+    assert_eq!(bp.size, Size::Four);
+    assert_eq!(bp.index, Index::Second);
+
+    let bp = Hwbp::first()
+        .with_watch(Condition::Execution, Size::One)
+        .expect("valid combination was rejected");
+    assert_eq!(bp.condition, Condition::Execution);
+
+    assert_eq!(
+        Hwbp::first().with_watch(Condition::Execution, Size::Two),
+        Err(BreakpointError::ExecutionSize)
+    );
+}
+
+#[test]
+fn size_valid_conditions() {
+    assert_eq!(Size::One.valid_conditions(), Condition::all());
+
+    let data = [Condition::Write, Condition::ReadWrite];
+    assert_eq!(Size::Two.valid_conditions(), data);
+    assert_eq!(Size::Four.valid_conditions(), data);
+    assert_eq!(Size::Eight.valid_conditions(), data);
+
+    // Agrees with validate on everything but the I/O check
+    for size in Size::all() {
+        for condition in Condition::all().into_iter().filter(|c| !c.is_io()) {
+            let bp = Hwbp::first().with_size(size).with_condition(condition);
+            assert_eq!(
+                size.valid_conditions().contains(&condition),
+                bp.validate() != Err(BreakpointError::ExecutionSize)
+            );
+        }
+    }
+}
+
+#[test]
+fn bits_tables() {
+    for condition in Condition::all() {
+        assert_eq!(Condition::from_bits(condition.as_bits()), Some(condition));
+    }
+    for size in Size::all() {
+        assert_eq!(Size::from_bits(size.as_bits()), Some(size));
+    }
+
+    // The tables agree with the match-based encoding, and cover every variant once
+    for (i, (condition, bits)) in Condition::BITS.into_iter().enumerate() {
+        assert_eq!(condition, Condition::all()[i]);
+        assert_eq!(condition.as_bits(), bits);
+    }
+    for (i, (size, bits)) in Size::BITS.into_iter().enumerate() {
+        assert_eq!(size, Size::all()[i]);
+        assert_eq!(size.as_bits(), bits);
+    }
+}
+
+#[test]
+fn from_bits_or_default() {
+    for bits in 0..4 {
+        assert_eq!(
+            Some(Condition::from_bits_or_default(bits)),
+            Condition::from_bits(bits)
+        );
+        assert_eq!(
+            Some(Size::from_bits_or_default(bits)),
+            Size::from_bits(bits)
+        );
+    }
+
+    for bits in [4, 0b111, 0x80, u8::MAX] {
+        assert_eq!(Condition::from_bits(bits), None);
+        assert_eq!(Condition::from_bits_or_default(bits), Condition::Execution);
+        assert_eq!(Size::from_bits(bits), None);
+        assert_eq!(Size::from_bits_or_default(bits), Size::One);
+    }
+}
+
+#[test]
+fn get_boxed() {
+    let boxed = HwbpContext::get_boxed().expect("failed to get context");
+
+    // The wrapped context sits at the start of the aligned allocation
+    let ptr: *const HwbpContext<CONTEXT> = &*boxed;
+    assert_eq!(ptr as usize % 16, 0);
+
+    let context = HwbpContext::get().expect("failed to get context");
+    assert_eq!(boxed.read_table(), context.read_table());
+}
+
+#[test]
+fn set_dr7_preserving() {
+    use crate::registers::DR7_MASK;
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    // Bit 10 is set even if the value doesn't have it
+    context.set_dr7_preserving(0);
+    assert_eq!(context.dr7().0, DR7_RESERVED);
+
+    context.set_dr7_preserving(0b1);
+    assert_eq!(context.dr7().0, DR7_RESERVED | 0b1);
+    assert!(context.dr7().enabled_local(Index::First));
+
+    // Reserved zeros stay clear, everything else is taken over
+    context.set_dr7_preserving(u64::MAX);
+    assert_eq!(context.dr7().0, DR7_MASK | DR7_RESERVED);
+    assert_eq!(context.dr7().0 & (1 << 12 | 1 << 14 | 1 << 15), 0);
+
+    context.set_dr7_preserving(0x000D_0001 | 0xFFFF_FFFF_0000_D000);
+    assert_eq!(context.dr7().0, 0x000D_0001 | DR7_RESERVED);
+    assert_eq!(context.dr7().condition(Index::First), Condition::Write);
+    assert_eq!(context.dr7().size(Index::First), Size::Four);
+}
+
+#[test]
+fn try_reserve() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    // Every call hands out a different slot, until they're all taken
+    let reserved: Vec<_> = std::iter::from_fn(|| context.try_reserve()).collect();
+    let indices: Vec<_> = reserved.iter().map(|bp| bp.index).collect();
+    assert_eq!(
+        indices,
+        [Index::First, Index::Second, Index::Third, Index::Fourth]
+    );
+    assert!(reserved.iter().all(|bp| bp.enabled));
+
+    assert_eq!(context.try_reserve(), None);
+    assert_eq!(context.enabled_breakpoints().count(), 4);
+}
+
+#[test]
+fn install_and_remove() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let bp = Hwbp::fourth()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write);
+
+    // Slots are handed out in order, regardless of the breakpoint's own index
+    let first = context.install(bp).expect("no unused breakpoints");
+    let second = context.install(bp).expect("no unused breakpoints");
+    assert_eq!(first.index(), Index::First);
+    assert_eq!(second.index(), Index::Second);
+    assert_ne!(first, second);
+    assert_eq!(context.breakpoint(Index::First), first.hwbp());
+    assert!(first.hwbp().enabled);
+
+    assert!(context.remove(first));
+    assert!(!context.breakpoint(Index::First).enabled);
+    assert!(context.breakpoint(Index::Second).enabled);
+
+    // Removing twice does nothing
+    assert!(!context.remove(first));
+
+    context.install(bp).expect("no unused breakpoints");
+    context.install(bp).expect("no unused breakpoints");
+    context.install(bp).expect("no unused breakpoints");
+    assert_eq!(context.install(bp), None);
+}
+
+#[test]
+fn remove_stale_token() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let token = context
+        .install(Hwbp::first().with_address(0x1000 as *const ()))
+        .expect("no unused breakpoints");
+
+    // The slot gets cleared and reused for something else behind the token's back
+    let other = Hwbp::first()
+        .with_address(0x2000 as *const ())
+        .with_enabled(true);
+    context.clear_breakpoint(token.index());
+    context.set_breakpoint(other);
+
+    assert!(!context.remove(token));
+    assert_eq!(context.breakpoint(Index::First), other);
+
+    // Or gets cleared and the exact same breakpoint is installed again
+    let bp = Hwbp::first().with_address(0x3000 as *const ());
+    context.clear_breakpoints();
+    let stale = context.install(bp).expect("no unused breakpoints");
+    context.clear_breakpoint(stale.index());
+    let fresh = context.install(bp).expect("no unused breakpoints");
+    assert_eq!(fresh.hwbp(), stale.hwbp());
+
+    // Only the newer token can remove it
+    assert!(!context.remove(stale));
+    assert!(context.breakpoint(fresh.index()).enabled);
+    assert!(context.remove(fresh));
+    assert!(!context.breakpoint(fresh.index()).is_in_use());
+}
+
+#[test]
+fn has_debug_registers() {
+    use winapi::um::winnt::{CONTEXT_CONTROL, CONTEXT_DEBUG_REGISTERS, CONTEXT_FULL};
+
+    let fetched = HwbpContext::get().expect("failed to get context");
+    assert!(fetched.has_debug_registers());
+
+    let mut record: CONTEXT = unsafe { std::mem::zeroed() };
+    assert!(!HwbpContext::from_context(&mut record).has_debug_registers());
+
+    // Other flags alone don't include the debug registers
+    record.ContextFlags = CONTEXT_FULL;
+    assert!(!HwbpContext::from_context(&mut record).has_debug_registers());
+
+    record.ContextFlags = CONTEXT_CONTROL | CONTEXT_DEBUG_REGISTERS;
+    assert!(HwbpContext::from_context(&mut record).has_debug_registers());
+}
+
+#[test]
+fn handle_hits() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint(Hwbp::second().with_address(0x2000 as *const ()));
+    context.set_breakpoint(Hwbp::fourth().with_address(0x4000 as *const ()));
+
+    // Two breakpoints hit at once, alongside the single-step flag
+    *context.dr6_mut().0 = 0b1010 | 1 << 14;
+
+    let mut seen = Vec::new();
+    assert_eq!(context.handle_hits(|bp| seen.push(bp)), 2);
+    assert_eq!(
+        seen,
+        [
+            context.breakpoint(Index::Second),
+            context.breakpoint(Index::Fourth)
+        ]
+    );
+
+    // Only the breakpoint flags are cleared
+    assert_eq!(context.dr6().breakpoints(), [false; 4]);
+    assert!(context.dr6().single_step());
+    assert_eq!(context.handle_hits(|_| panic!("nothing left to handle")), 0);
+}
+
+#[test]
+fn which_fired() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let first = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    let second = Hwbp::second()
+        .with_address(0x2000 as *const ())
+        .with_condition(Condition::ReadWrite)
+        .with_enabled(true);
+    let foreign = Hwbp::third()
+        .with_address(0x3000 as *const ())
+        .with_enabled(true);
+    for bp in [first, second, foreign] {
+        context.set_breakpoint(bp);
+    }
+
+    // Nothing triggered
+    assert_eq!(context.which_fired(&[first, second]), None);
+
+    *context.dr6_mut().0 = 0b0010;
+    assert_eq!(context.which_fired(&[first, second]), Some(second));
+    assert_eq!(context.which_fired(&[first]), None);
+
+    // Only the caller's breakpoints are returned, even if others triggered alongside them
+    *context.dr6_mut().0 = 0b0101;
+    assert_eq!(context.which_fired(&[first, second]), Some(first));
+    *context.dr6_mut().0 = 0b0100;
+    assert_eq!(context.which_fired(&[first, second]), None);
+
+    // A slot reused for something else doesn't match anymore
+    context.set_breakpoint(second.with_address(0x2008 as *const ()));
+    *context.dr6_mut().0 = 0b0010;
+    assert_eq!(context.which_fired(&[first, second]), None);
+}
+
+#[test]
+fn has_foreign_breakpoints() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let mine = Hwbp::second()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_enabled(true);
+    context.set_breakpoint(mine);
+
+    assert!(!context.has_foreign_breakpoints(&[mine]));
+    assert!(context.has_foreign_breakpoints(&[]));
+
+    // Disabled breakpoints don't count, no matter what they look like
+    context.set_breakpoint(Hwbp::third().with_address(0x2000 as *const ()));
+    assert!(!context.has_foreign_breakpoints(&[mine]));
+
+    // A different breakpoint in the same slot isn't ours anymore
+    context.set_breakpoint(mine.with_size(Size::Four));
+    assert!(context.has_foreign_breakpoints(&[mine]));
+    context.set_breakpoint(mine);
+
+    // And neither is one only enabled through the global bit
+    context.set_breakpoint_global(
+        Hwbp::fourth()
+            .with_address(0x3000 as *const ())
+            .with_enabled(true),
+    );
+    assert!(context.has_foreign_breakpoints(&[mine]));
+}
+
+#[test]
+fn set_breakpoint_at() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let bp = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_size(Size::Two)
+        .with_enabled(true);
+    context.set_breakpoint_at(Index::Third, bp);
+
+    // The breakpoint ends up in the passed slot, and the slot in its own index stays empty
+    assert_eq!(context.breakpoint(Index::Third), bp.at_index(Index::Third));
+    assert!(!context.breakpoint(Index::First).enabled);
+    assert_eq!(context.breakpoint(Index::First).address, std::ptr::null());
+}
+
+#[test]
+fn clear_breakpoints_returns_previous() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    let first = Hwbp::first()
+        .with_address(0x1000 as *const ())
+        .with_condition(Condition::Write)
+        .with_size(Size::Four)
+        .with_enabled(true);
+    let third = Hwbp::third()
+        .with_address(0x3000 as *const ())
+        .with_condition(Condition::Execution);
+    context.set_breakpoint(first);
+    context.set_breakpoint(third);
+
+    let before = context.read_table();
+    let cleared = context.clear_breakpoints();
+    assert_eq!(cleared, before);
+    assert_eq!(cleared[Index::First as usize], first);
+    assert_eq!(cleared[Index::Third as usize], third);
+
+    // Everything is cleared now, so clearing again returns empty breakpoints
+    assert_eq!(context.clear_breakpoints(), context.read_table());
+    assert!(context.clear_breakpoints().iter().all(|bp| !bp.enabled));
+
+    // And the returned table restores the previous state
+    context.write_table(cleared);
+    assert_eq!(context.read_table(), before);
+}
+
+#[test]
+fn table_roundtrip() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    // Indices are fixed to the slot each breakpoint is written to
+    let table = [
+        Hwbp::fourth()
+            .with_address(0x1000 as *const ())
+            .with_enabled(true),
+        Hwbp::first().with_condition(Condition::Write),
+        Hwbp::first()
+            .with_address(0x2000 as *const ())
+            .with_size(Size::Four)
+            .with_enabled(true),
+        Hwbp::second().with_condition(Condition::Execution),
+    ];
+    context.write_table(table);
+
+    let read = context.read_table();
+    for (index, (read, written)) in [Index::First, Index::Second, Index::Third, Index::Fourth]
+        .into_iter()
+        .zip(read.into_iter().zip(table))
+    {
+        assert_eq!(read, written.at_index(index));
+    }
+
+    // Writing what was read changes nothing
+    let dr7 = context.dr7();
+    context.write_table(read);
+    assert_eq!(context.read_table(), read);
+    assert!(context.dr7() == dr7);
+}
+
+#[test]
+fn exception_cause() {
+    use crate::{DebugCause, PseudoUsize};
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint(Hwbp::third().with_enabled(true));
+
+    let mut cause = |dr6: PseudoUsize| {
+        *context.dr6_mut().0 = dr6 | 1 << 16;
+        context.exception_cause()
+    };
+
+    assert_eq!(cause(0), DebugCause::Unknown);
+    assert_eq!(cause(0b0100), DebugCause::Breakpoint(Index::Third));
+    assert_eq!(cause(1 << 14), DebugCause::SingleStep);
+    assert_eq!(cause(1 << 15), DebugCause::TaskSwitch);
+    assert_eq!(cause(1 << 13), DebugCause::DebugRegisterAccess);
+
+    // Flags of disabled breakpoints are ignored
+    assert_eq!(cause(0b0001), DebugCause::Unknown);
+    assert_eq!(cause(0b0001 | 1 << 14), DebugCause::SingleStep);
+
+    // A breakpoint takes precedence over single-stepping
+    assert_eq!(
+        cause(0b0100 | 1 << 14),
+        DebugCause::Breakpoint(Index::Third)
+    );
+}
+
+#[test]
+fn enable_verified() {
+    use crate::context::{ApplyContext, ApplyWith, FetchWith};
+    use crate::HwbpError;
+
+    // Pretends to apply the context, without doing anything
+    struct Sabotaged;
+    impl ApplyContext for Sabotaged {
+        fn apply_context(self, _: &CONTEXT) -> Result<(), HwbpError> {
+            Ok(())
+        }
+    }
+
+    static WATCHED: u8 = 0;
+    let bp = Hwbp::fourth()
+        .with_address(&WATCHED)
+        .with_condition(Condition::Write);
+
+    unsafe {
+        // Make sure the slot starts out empty
+        let mut context = HwbpContext::get().expect("failed to get context");
+        context.clear_breakpoint(Index::Fourth);
+        context.apply().expect("failed to apply context");
+
+        assert_eq!(
+            bp.enable_verified_with(FetchWith::GetThreadContext, Sabotaged),
+            Err(HwbpError::VerificationFailed)
+        );
+
+        let enabled = bp
+            .enable_verified_with(FetchWith::GetThreadContext, ApplyWith::SetThreadContext)
+            .expect("failed to enable breakpoint");
+        assert_eq!(enabled, bp.with_enabled(true));
+
+        bp.disable().expect("failed to disable breakpoint");
+    }
+}
+
+#[test]
+fn apply_debug_registers() {
+    use crate::context::ApplyContext;
+    use crate::HwbpError;
+    use winapi::um::winnt::{CONTEXT_ALL, CONTEXT_DEBUG_REGISTERS};
+
+    // Checks which parts of the context would be applied, without applying anything
+    struct OnlyDebugRegisters;
+    impl ApplyContext for OnlyDebugRegisters {
+        fn apply_context(self, context: &CONTEXT) -> Result<(), HwbpError> {
+            assert_eq!(context.ContextFlags, CONTEXT_DEBUG_REGISTERS);
+            Ok(())
+        }
+    }
+
+    let mut record: CONTEXT = unsafe { std::mem::zeroed() };
+    record.ContextFlags = CONTEXT_ALL;
+    #[cfg(target_arch = "x86_64")]
+    {
+        record.Rax = 0x1234;
+    }
+    #[cfg(target_arch = "x86")]
+    {
+        record.Eax = 0x1234;
+    }
+
+    let mut context = HwbpContext::from_context(&mut record);
+    context.set_breakpoint(Hwbp::first().with_enabled(true));
+    unsafe { context.apply_debug_registers_with(OnlyDebugRegisters) }
+        .expect("failed to apply context");
+
+    // The general purpose registers are left alone, only the flags changed
+    assert_eq!(record.ContextFlags, CONTEXT_DEBUG_REGISTERS);
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(record.Rax, 0x1234);
+    #[cfg(target_arch = "x86")]
+    assert_eq!(record.Eax, 0x1234);
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn stack_pointer() {
+    let mut record: CONTEXT = unsafe { std::mem::zeroed() };
+    let mut context = HwbpContext::from_context(&mut record);
+
+    // A fake stack, with the return address on top
+    let stack: [usize; 2] = [0x401000, 0];
+    context.set_stack_pointer(stack.as_ptr() as usize);
+
+    assert_eq!(context.stack_pointer(), stack.as_ptr() as usize);
+    assert_eq!(unsafe { context.return_address() }, 0x401000);
+
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(record.Rsp, stack.as_ptr() as u64);
+    #[cfg(target_arch = "x86")]
+    assert_eq!(record.Esp, stack.as_ptr() as u32);
+}
+
+#[test]
+#[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+fn segment() {
+    use crate::Segment;
+
+    let mut record: CONTEXT = unsafe { std::mem::zeroed() };
+    record.SegCs = 0x33;
+    record.SegDs = 0x2b;
+    record.SegEs = 0x2b;
+    record.SegFs = 0x53;
+    record.SegGs = 0x2b;
+    record.SegSs = 0x2b;
+
+    let context = HwbpContext::from_context(&mut record);
+    assert_eq!(context.segment(Segment::Cs), 0x33);
+    assert_eq!(context.segment(Segment::Ds), 0x2b);
+    assert_eq!(context.segment(Segment::Fs), 0x53);
+    assert_eq!(context.segment(Segment::Ss), 0x2b);
+
+    // `RtlCaptureContext` captures the segments, and the code segment is never null
+    let live = HwbpContext::get_with(crate::context::FetchWith::RtlCaptureContext)
+        .expect("failed to get context");
+    assert_ne!(live.segment(Segment::Cs), 0);
+}
+
+#[test]
+fn dr7_fields() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    context.set_breakpoint(
+        Hwbp::first()
+            .with_size(Size::Two)
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    context.set_breakpoint(Hwbp::third().with_condition(Condition::Execution));
+    context.set_breakpoint_global(
+        Hwbp::fourth()
+            .with_size(Size::Four)
+            .with_condition(Condition::ReadWrite)
+            .with_enabled(true),
+    );
+
+    // Decoding agrees with the per-index accessors
+    let dr7 = context.dr7();
+    let fields = dr7.decode();
+    for index in [Index::First, Index::Second, Index::Third, Index::Fourth] {
+        let i = index as usize;
+        assert_eq!(fields.locals[i], dr7.enabled_local(index));
+        assert_eq!(fields.globals[i], dr7.enabled_global(index));
+        assert_eq!(fields.conditions[i], dr7.condition(index));
+        assert_eq!(fields.sizes[i], dr7.size(index));
+    }
+
+    // And composing the fields again gives back the same value, plus the reserved bit
+    assert_eq!(Dr7::from_fields(fields), dr7.0 | DR7_RESERVED);
+    assert_eq!(Dr7(Dr7::from_fields(fields)).decode(), fields);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_events() {
+    use crate::context::{ApplyWith, FetchWith};
+    use std::sync::Mutex;
+
+    static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    struct Capture;
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target().starts_with("hwbp")
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) && record.level() == log::Level::Debug {
+                EVENTS.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    log::set_logger(&Capture).expect("logger already set");
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let context =
+        HwbpContext::get_with(FetchWith::GetThreadContext).expect("failed to get context");
+    unsafe { context.apply_with(ApplyWith::SetThreadContext) }.expect("failed to apply context");
+
+    let events = EVENTS.lock().unwrap();
+    assert!(events.contains(&"fetch with GetThreadContext: Ok(())".to_owned()));
+    assert!(events.contains(&"apply with SetThreadContext: Ok(())".to_owned()));
+}
+
+#[test]
+fn triggered_or_stale() {
+    use crate::StaleDr6;
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    // Clean
+    *context.dr6_mut().0 = 0b0100;
+    assert_eq!(context.triggered_or_stale(), Ok(Index::Third));
+
+    // Empty
+    context.dr6_mut().reset();
+    assert_eq!(context.triggered_or_stale(), Err(StaleDr6::Empty));
+
+    // Multiple
+    *context.dr6_mut().0 = 0b1001;
+    assert_eq!(context.triggered_or_stale(), Err(StaleDr6::Multiple));
+}
+
+#[test]
+fn access_trap() {
+    let mut dr7 = Dr7(DR7_RESERVED);
+    assert!(!dr7.access_trap_armed());
+
+    dr7.arm_access_trap();
+    assert!(dr7.access_trap_armed());
+    assert_eq!(dr7.0, DR7_RESERVED | 1 << 13);
+
+    dr7.disarm_access_trap();
+    assert!(!dr7.access_trap_armed());
+    assert_eq!(dr7.0, DR7_RESERVED);
+
+    // The processor reports the trap through Dr6's BD flag
+    assert!(Dr6(1u64 << 13 | 1 << 16).was_access_trap());
+    assert!(!Dr6(0b1111u64 | 1 << 14 | 1 << 16).was_access_trap());
+}
+
+#[test]
+fn decode_slot() {
+    use crate::registers;
+
+    let bps = [
+        Hwbp::first()
+            .with_condition(Condition::Write)
+            .with_size(Size::Two)
+            .with_enabled(true),
+        Hwbp::second()
+            .with_condition(Condition::Execution)
+            .with_size(Size::One),
+        Hwbp::third()
+            .with_condition(Condition::ReadWrite)
+            .with_size(Size::Eight)
+            .with_enabled(true),
+        Hwbp::fourth()
+            .with_condition(Condition::IoReadWrite)
+            .with_size(Size::Four)
+            .with_enabled(true),
+    ];
+
+    let dr7 = raw::compose_dr7(bps) as u64;
+    for bp in bps {
+        assert_eq!(
+            registers::decode_slot(dr7, bp.index),
+            (bp.enabled, bp.condition, bp.size)
+        );
+    }
+}
+
+#[test]
+fn from_bytes() {
+    use std::mem::size_of;
+
+    let mut original = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+    original.set_breakpoint(
+        Hwbp::second()
+            .with_address(0x1000 as *const ())
+            .with_condition(Condition::Write)
+            .with_enabled(true),
+    );
+    *original.dr6_mut().0 = 0b0010;
+    let original = original.into_context();
+
+    // Place the context at an odd offset, so it's misaligned
+    let mut buffer = vec![0u8; size_of::<CONTEXT>() + 1];
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            (&original as *const CONTEXT).cast::<u8>(),
+            size_of::<CONTEXT>(),
+        )
+    };
+    buffer[1..].copy_from_slice(bytes);
+
+    let context = HwbpContext::from_bytes(&buffer[1..]).expect("buffer is large enough");
+    assert_eq!(
+        context.read_table(),
+        HwbpContext::from_context(&original).read_table()
+    );
+    assert_eq!(context.triggered_or_stale(), Ok(Index::Second));
+
+    // Too short
+    assert!(HwbpContext::from_bytes(&buffer[2..]).is_none());
+    assert!(HwbpContext::from_bytes(&[]).is_none());
+}
+
+#[test]
+fn take_hits() {
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    *context.dr6_mut().0 = 0b1010 | 1 << 14;
+    assert_eq!(context.take_hits(), [false, true, false, true]);
+    assert_eq!(context.dr6().0, 1 << 16);
+
+    // Nothing is left to take
+    assert_eq!(context.take_hits(), [false; 4]);
+}
+
+#[test]
+fn dr6_status() {
+    use crate::registers::Dr6Status;
+
+    let mut context = HwbpContext::from_context(unsafe { std::mem::zeroed::<CONTEXT>() });
+
+    context.dr6_mut().reset();
+    assert_eq!(
+        context.dr6_status(),
+        Dr6Status {
+            triggered: [false; 4],
+            single_step: false,
+            task_switch: false,
+            debug_register_access: false,
+            rtm: true,
+        }
+    );
+
+    *context.dr6_mut().0 = 0b1010 | 1 << 14;
+    assert_eq!(
+        context.dr6_status(),
+        Dr6Status {
+            triggered: [false, true, false, true],
+            single_step: true,
+            task_switch: false,
+            debug_register_access: false,
+            rtm: false,
+        }
+    );
+
+    *context.dr6_mut().0 = 1 << 13 | 1 << 15 | 1 << 16;
+    assert_eq!(
+        context.dr6_status(),
+        Dr6Status {
+            triggered: [false; 4],
+            single_step: false,
+            task_switch: true,
+            debug_register_access: true,
+            rtm: true,
+        }
+    );
+}